@@ -0,0 +1,31 @@
+// Prints the day 3 claim conflict report:
+//
+//     cargo run --release --example conflicts -- [text|csv] [input file]
+extern crate aoc2018;
+
+use aoc2018::day3::{to_claims, ConflictReport};
+use std::env;
+use std::fs;
+use std::io;
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let format = args.first().map_or("text", |f| f.as_str());
+    let path = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| "input/2018/day3.txt".to_owned());
+    let inp = fs::read_to_string(path)?;
+    let report = ConflictReport::new(&to_claims(inp.trim_end()));
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match format {
+        "csv" => report.write_csv(&mut out),
+        "text" => report.write_text(&mut out),
+        _ => {
+            eprintln!("usage: conflicts [text|csv] [input file]");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Writes a picture of one of the days' states:
+//
+//     cargo run --release --example export -- <day> <out.png|ppm|svg> [scale] [input file]
+//
+// Day 18 takes the number of minutes to run as an extra trailing argument.
+extern crate aoc2018;
+
+use aoc2018::image::{self, Format};
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        eprintln!("usage: export <3|6|10|11|18> <out.png|ppm|svg> [scale] [input file] [minutes]");
+        std::process::exit(1);
+    }
+    let day: u32 = args[0].parse().expect("day should be a number");
+    let out_path = &args[1];
+    let format = Format::from_path(out_path).expect("output should be .png, .ppm or .svg");
+    let scale = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(1);
+    let path = args
+        .get(3)
+        .cloned()
+        .unwrap_or_else(|| format!("input/2018/day{}.txt", day));
+    let inp = fs::read_to_string(path)?;
+    let inp = inp.trim_end();
+
+    let raster = match day {
+        3 => aoc2018::day3::render_overlaps(inp, &aoc2018::day3::overlap_palette(8), 2000),
+        6 => aoc2018::day6::render_ownership(
+            inp,
+            aoc2018::day6::OWNERSHIP_PALETTE,
+            &image::id_palette(64),
+        ),
+        10 => aoc2018::day10::render_message(inp, [image::BLACK, image::WHITE]),
+        11 => aoc2018::day11::render_power(inp, aoc2018::day11::power_palette()),
+        18 => {
+            let minutes = args.get(4).and_then(|m| m.parse().ok()).unwrap_or(10);
+            aoc2018::day18::render_generation(inp, minutes, aoc2018::day18::FOREST_PALETTE)
+        }
+        _ => {
+            eprintln!("day {} has nothing to export", day);
+            std::process::exit(1);
+        }
+    };
+
+    let mut out = BufWriter::new(File::create(out_path)?);
+    raster.scaled(scale).write(format, &mut out)
+}
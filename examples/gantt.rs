@@ -0,0 +1,48 @@
+// Draws the day 7 schedule as a Gantt chart, highlighting the critical path:
+//
+//     cargo run --release --example gantt -- [text|svg] [input file] [workers] [base duration]
+extern crate aoc2018;
+
+use aoc2018::day7::{base_duration, critical_path, parse_graph, schedule};
+use aoc2018::toposort::TieBreak;
+use std::env;
+use std::fs;
+use std::io;
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let format = args.first().map_or("text", |f| f.as_str());
+    let path = args
+        .get(1)
+        .cloned()
+        .unwrap_or_else(|| "input/2018/day7.txt".to_owned());
+    let workers = args
+        .get(2)
+        .map_or(5, |w| w.parse().expect("bad worker count"));
+    let base = args
+        .get(3)
+        .map_or(60, |b| b.parse().expect("bad base duration"));
+
+    let graph = parse_graph(&fs::read_to_string(path)?);
+    let plan = schedule(
+        &graph,
+        workers,
+        &TieBreak::Lexicographic,
+        base_duration(base),
+        |_, _| true,
+    )
+    .unwrap_or_else(|e| panic!("can't schedule: {:?}", e));
+    let critical = critical_path(&graph, base_duration(base)).unwrap();
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match format {
+        // About 100 columns wide
+        "text" => plan.write_gantt_text(&mut out, plan.total_time / 100 + 1, &critical),
+        "svg" => plan.write_gantt_svg(&mut out, &critical),
+        _ => {
+            eprintln!("usage: gantt [text|svg] [input file] [workers] [base duration]");
+            std::process::exit(1);
+        }
+    }
+}
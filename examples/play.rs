@@ -0,0 +1,41 @@
+// Animates one of the simulation days in the terminal:
+//
+//     cargo run --release --example play -- <day> [fps] [input file]
+//
+// Commands are read from stdin a line at a time (press enter after each).
+extern crate aoc2018;
+
+use aoc2018::player::PlayerConfig;
+use std::env;
+use std::fs;
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let day: u32 = match args.first().and_then(|d| d.parse().ok()) {
+        Some(d) => d,
+        None => {
+            eprintln!("usage: play <10|12|13|18> [fps] [input file]");
+            std::process::exit(1);
+        }
+    };
+    let mut config = PlayerConfig::default();
+    if let Some(fps) = args.get(1).and_then(|f| f.parse().ok()) {
+        config.fps = fps;
+    }
+    let path = args
+        .get(2)
+        .cloned()
+        .unwrap_or_else(|| format!("input/2018/day{}.txt", day));
+    let inp = fs::read_to_string(path)?;
+
+    match day {
+        10 => aoc2018::day10::animate(inp.trim_end(), config),
+        12 => aoc2018::day12::animate(inp.trim_end(), config),
+        13 => aoc2018::day13::animate(&inp, config),
+        18 => aoc2018::day18::animate(inp.trim_end(), config),
+        _ => {
+            eprintln!("day {} has nothing to animate", day);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::iter::FromIterator;
+
+// A Zipper inspired circular buffer with a focus
+// the cw vec is 'backwards' to enable efficiently
+// pushing and popping of the elements
+//
+// Going clockwise from the focus the items are the focus, cw from the back
+// to the front, then ccw from the front to the back. When one side runs out
+// the nearer half of the other side is moved over, so moving the focus is
+// amortised O(1) however it wanders.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CircBuffer<T> {
+    ccw: Vec<T>,
+    focus: Option<T>,
+    cw: Vec<T>,
+}
+
+impl<T> Default for CircBuffer<T> {
+    fn default() -> Self {
+        CircBuffer::new()
+    }
+}
+
+impl<T> CircBuffer<T> {
+    pub fn new() -> Self {
+        CircBuffer::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        CircBuffer {
+            ccw: Vec::with_capacity(capacity / 2),
+            focus: None,
+            cw: Vec::with_capacity(capacity / 2),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.focus
+            .as_ref()
+            .map_or(0, |_| 1 + self.cw.len() + self.ccw.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.focus.is_none()
+    }
+
+    pub fn focus(&self) -> Option<&T> {
+        self.focus.as_ref()
+    }
+
+    pub fn focus_mut(&mut self) -> Option<&mut T> {
+        self.focus.as_mut()
+    }
+
+    // Insert the new element in the focus, pushing the current focus cw
+    pub fn insert(&mut self, elem: T) {
+        if let Some(old) = self.focus.replace(elem) {
+            self.cw.push(old);
+        }
+    }
+
+    // Remove the focus and slide the next item cw around in its place
+    pub fn remove(&mut self) -> Option<T> {
+        let res = self.focus.take();
+        if self.cw.is_empty() {
+            self.refill_cw();
+        }
+        self.focus = self.cw.pop();
+        res
+    }
+
+    // Rotates the focus n items clockwise, or counter clockwise for negative
+    // n, going whichever way round is shorter
+    pub fn rotate(&mut self, n: isize) {
+        let len = self.len();
+        if len < 2 {
+            return;
+        }
+        let steps = n.rem_euclid(len as isize) as usize;
+        if steps <= len / 2 {
+            for _ in 0..steps {
+                self.clockwise();
+            }
+        } else {
+            for _ in steps..len {
+                self.counter_clockwise();
+            }
+        }
+    }
+
+    // Rotates the focus one item clockwise
+    pub fn clockwise(&mut self) {
+        if let Some(focus) = self.focus.take() {
+            self.ccw.push(focus);
+            if self.cw.is_empty() {
+                self.refill_cw();
+            }
+            self.focus = self.cw.pop();
+        }
+    }
+
+    // Rotates the focus one item counter_clockwise
+    pub fn counter_clockwise(&mut self) {
+        if let Some(focus) = self.focus.take() {
+            self.cw.push(focus);
+            if self.ccw.is_empty() {
+                self.refill_ccw();
+            }
+            self.focus = self.ccw.pop();
+        }
+    }
+
+    // The items just clockwise of an empty cw are at the front of ccw
+    fn refill_cw(&mut self) {
+        let half = self.ccw.len().div_ceil(2);
+        self.cw.extend(self.ccw.drain(..half).rev());
+    }
+
+    fn refill_ccw(&mut self) {
+        let half = self.cw.len().div_ceil(2);
+        self.ccw.extend(self.cw.drain(..half).rev());
+    }
+
+    // Every item, clockwise starting from the focus
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.focus
+            .iter()
+            .chain(self.cw.iter().rev())
+            .chain(self.ccw.iter())
+    }
+}
+
+// The first item ends up in the focus, with the rest following clockwise
+impl<T> FromIterator<T> for CircBuffer<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut iter = iter.into_iter();
+        let focus = iter.next();
+        let mut cw: Vec<T> = iter.collect();
+        cw.reverse();
+        CircBuffer {
+            ccw: Vec::new(),
+            focus,
+            cw,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // The model keeps the focus at the front and goes clockwise from there
+    fn check(buffer: &CircBuffer<u32>, model: &VecDeque<u32>) {
+        assert_eq!(buffer.len(), model.len());
+        assert_eq!(buffer.is_empty(), model.is_empty());
+        assert_eq!(buffer.focus(), model.front());
+        assert!(buffer.iter().eq(model.iter()));
+    }
+
+    fn rotate_model(model: &mut VecDeque<u32>, n: isize) {
+        if !model.is_empty() {
+            let steps = n.rem_euclid(model.len() as isize) as usize;
+            model.rotate_left(steps);
+        }
+    }
+
+    #[test]
+    fn matches_vecdeque() {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+
+        for _ in 0..500 {
+            let mut buffer = CircBuffer::new();
+            let mut model = VecDeque::new();
+            for _ in 0..300 {
+                match rand(6) {
+                    0 | 1 => {
+                        let v = rand(1000) as u32;
+                        buffer.insert(v);
+                        model.push_front(v);
+                    }
+                    2 => assert_eq!(buffer.remove(), model.pop_front()),
+                    3 => {
+                        // Often several times the length, either way round
+                        let n = rand(81) as isize - 40;
+                        buffer.rotate(n);
+                        rotate_model(&mut model, n);
+                    }
+                    4 => {
+                        buffer.clockwise();
+                        rotate_model(&mut model, 1);
+                    }
+                    _ => {
+                        buffer.counter_clockwise();
+                        rotate_model(&mut model, -1);
+                    }
+                }
+                check(&buffer, &model);
+            }
+
+            // Then empty it, rotating as we go
+            while !model.is_empty() {
+                let n = rand(21) as isize - 10;
+                buffer.rotate(n);
+                rotate_model(&mut model, n);
+                assert_eq!(buffer.remove(), model.pop_front());
+                check(&buffer, &model);
+            }
+            assert_eq!(buffer.remove(), None);
+            buffer.rotate(3);
+            buffer.clockwise();
+            buffer.counter_clockwise();
+            check(&buffer, &model);
+        }
+    }
+
+    #[test]
+    fn collects_clockwise_from_the_focus() {
+        let buffer: CircBuffer<u32> = (1..=5).collect();
+        assert!(buffer.iter().cloned().eq(1..=5));
+        let mut model: VecDeque<u32> = (1..=5).collect();
+        let mut buffer = buffer;
+        buffer.rotate(-7);
+        rotate_model(&mut model, -7);
+        check(&buffer, &model);
+    }
+}
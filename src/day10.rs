@@ -1,3 +1,7 @@
+use crate::image::{Raster, Rgb};
+use crate::player::{Animation, Player, PlayerConfig};
+use std::collections::HashSet;
+
 #[derive(Debug, Clone, Copy)]
 struct Point {
     x: i32,
@@ -27,6 +31,113 @@ fn parse_points(inp: &str) -> Vec<Point> {
 
 #[aoc(day10, part1)]
 fn part1(points: &[Point]) -> String {
+    let (points, _) = converge_closed_form(points);
+    show_points(&points);
+    read_message(&points).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[aoc(day10, part2)]
+fn part2(points: &[Point]) -> usize {
+    converge_closed_form(points).1
+}
+
+const GLYPH_WIDTH: usize = 6;
+const GLYPH_HEIGHT: usize = 10;
+// Letters are drawn with two blank columns between them
+const GLYPH_STRIDE: usize = GLYPH_WIDTH + 2;
+
+#[rustfmt::skip]
+const FONT: [(char, [&str; GLYPH_HEIGHT]); 15] = [
+    ('A', ["..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#"]),
+    ('B', ["#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#", "#....#", "#####."]),
+    ('C', [".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#....#", ".####."]),
+    ('E', ["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('F', ["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('G', [".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#", "#...##", ".###.#"]),
+    ('H', ["#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#", "#....#"]),
+    ('J', ["...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.", "#...#.", ".###.."]),
+    ('K', ["#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..", "#...#.", "#....#"]),
+    ('L', ["#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('N', ["#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##", "#...##", "#....#"]),
+    ('P', ["#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('R', ["#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.", "#....#", "#....#"]),
+    ('X', ["#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.", "#....#", "#....#"]),
+    ('Z', ["######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....", "#.....", "######"]),
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum OcrError {
+    BadHeight(usize),
+    BadWidth(usize),
+    UnknownGlyph { index: usize, rows: Vec<String> },
+}
+
+impl std::fmt::Display for OcrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OcrError::BadHeight(h) => {
+                write!(f, "message is {} rows tall, expected {}", h, GLYPH_HEIGHT)
+            }
+            OcrError::BadWidth(w) => write!(
+                f,
+                "message is {} columns wide, which doesn't split into letters",
+                w
+            ),
+            OcrError::UnknownGlyph { index, rows } => {
+                writeln!(f, "unrecognised letter at position {}:", index)?;
+                for r in rows {
+                    writeln!(f, "{}", r)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Runs the sky until the message appears, returning it along with the number
+// of seconds that took
+pub fn decode_message(inp: &str) -> Result<(String, usize), OcrError> {
+    let (points, seconds) = converge_closed_form(&parse_points(inp));
+    read_message(&points).map(|msg| (msg, seconds))
+}
+
+// Rasterises the converged points and matches each letter against FONT
+fn read_message(points: &[Point]) -> Result<String, OcrError> {
+    let sky = Sky::new(points.to_owned());
+    let (min_x, min_y, max_x, max_y) = sky.bounds();
+    let height = (max_y - min_y + 1) as usize;
+    let width = (max_x - min_x + 1) as usize;
+    if height != GLYPH_HEIGHT {
+        return Err(OcrError::BadHeight(height));
+    }
+    // The last letter has no gap after it
+    let padded = width + GLYPH_STRIDE - GLYPH_WIDTH;
+    if !padded.is_multiple_of(GLYPH_STRIDE) {
+        return Err(OcrError::BadWidth(width));
+    }
+
+    let letters = padded / GLYPH_STRIDE;
+    (0..letters)
+        .map(|index| {
+            let left = min_x + (index * GLYPH_STRIDE) as i64;
+            let rows: Vec<String> = (min_y..=max_y)
+                .map(|y| {
+                    (left..left + GLYPH_WIDTH as i64)
+                        .map(|x| if sky.cell(x, y) == '#' { '#' } else { '.' })
+                        .collect()
+                })
+                .collect();
+            FONT.iter()
+                .find(|(_, glyph)| glyph.iter().zip(rows.iter()).all(|(g, r)| g == r))
+                .map(|(c, _)| *c)
+                .ok_or(OcrError::UnknownGlyph { index, rows })
+        })
+        .collect()
+}
+
+// Steps the points until the y-extent is smallest, returning them along with
+// the number of seconds that took
+fn converge(points: &[Point]) -> (Vec<Point>, usize) {
     let mut score: usize = std::usize::MAX;
     let mut points = points.to_owned();
     let mut step_count = 0;
@@ -39,13 +150,70 @@ fn part1(points: &[Point]) -> String {
             step_count -= 1; // We've gone a step too far, and are now stepping back
             println!("steps: {}, score: {}", step_count, score);
             uniterate_points(&mut points);
-            show_points(&points);
-            break;
+            return (points, step_count);
         } else {
             score = new_score;
         }
     }
-    "reclrnze".to_owned()
+}
+
+#[aoc(day10, part2, stepping)]
+fn part2_stepping(points: &[Point]) -> usize {
+    converge(points).1
+}
+
+// The spread of the points, summed over both axes, is a quadratic in t:
+//   sum((x + v_x t - mean_x)^2) = Var(x) + 2t Cov(x, v_x) + t^2 Var(v_x)
+// so it's smallest at t = -Cov(x, v) / Var(v). That's the least squares
+// meeting time, which should land within a step or two of the message.
+fn estimate_meeting_time(points: &[Point]) -> f64 {
+    let n = points.len() as f64;
+    let mean = |f: &dyn Fn(&Point) -> i32| points.iter().map(|p| f64::from(f(p))).sum::<f64>() / n;
+    let (mx, my) = (mean(&|p| p.x), mean(&|p| p.y));
+    let (mvx, mvy) = (mean(&|p| p.v_x), mean(&|p| p.v_y));
+
+    let (mut cov, mut var) = (0.0, 0.0);
+    for p in points {
+        let (dvx, dvy) = (f64::from(p.v_x) - mvx, f64::from(p.v_y) - mvy);
+        cov += (f64::from(p.x) - mx) * dvx + (f64::from(p.y) - my) * dvy;
+        var += dvx * dvx + dvy * dvy;
+    }
+    if var == 0.0 {
+        0.0
+    } else {
+        -cov / var
+    }
+}
+
+fn points_at(points: &[Point], t: i64) -> Vec<Point> {
+    points
+        .iter()
+        .map(|p| Point {
+            x: (i64::from(p.x) + i64::from(p.v_x) * t) as i32,
+            y: (i64::from(p.y) + i64::from(p.v_y) * t) as i32,
+            ..*p
+        })
+        .collect()
+}
+
+// Jumps straight to the estimated meeting time and then walks downhill on the
+// same y-extent score converge uses. The extent is convex in t, so like
+// converge this stops at the last second of its smallest value, which only
+// goes on forever if every point has the same vertical speed.
+fn converge_closed_form(points: &[Point]) -> (Vec<Point>, usize) {
+    let score_at = |t: i64| calc_score(&points_at(points, t));
+    let mut t = std::cmp::max(0, estimate_meeting_time(points).round() as i64);
+    if points.iter().all(|p| p.v_y == points[0].v_y) {
+        return (points_at(points, t), t as usize);
+    }
+
+    while score_at(t + 1) <= score_at(t) {
+        t += 1;
+    }
+    while t > 0 && score_at(t - 1) < score_at(t) {
+        t -= 1;
+    }
+    (points_at(points, t), t as usize)
 }
 
 fn iterate_points(points: &mut [Point]) {
@@ -95,3 +263,170 @@ fn show_points(points: &[Point]) {
     println!();
     println!();
 }
+
+#[derive(Debug, Clone)]
+struct Sky {
+    points: Vec<Point>,
+    lit: HashSet<(i64, i64)>,
+    seconds: usize,
+}
+
+impl Sky {
+    fn new(points: Vec<Point>) -> Sky {
+        let lit = points.iter().map(|p| (p.x as i64, p.y as i64)).collect();
+        Sky {
+            points,
+            lit,
+            seconds: 0,
+        }
+    }
+}
+
+impl Animation for Sky {
+    fn bounds(&self) -> (i64, i64, i64, i64) {
+        let xs = self.points.iter().map(|p| p.x as i64);
+        let ys = self.points.iter().map(|p| p.y as i64);
+        (
+            xs.clone().min().unwrap_or(0),
+            ys.clone().min().unwrap_or(0),
+            xs.max().unwrap_or(0),
+            ys.max().unwrap_or(0),
+        )
+    }
+
+    fn cell(&self, x: i64, y: i64) -> char {
+        if self.lit.contains(&(x, y)) {
+            '#'
+        } else {
+            ' '
+        }
+    }
+
+    // The sky keeps moving forever; it's up to the viewer to stop at the message
+    fn step(&mut self) -> bool {
+        iterate_points(&mut self.points);
+        self.lit = self
+            .points
+            .iter()
+            .map(|p| (p.x as i64, p.y as i64))
+            .collect();
+        self.seconds += 1;
+        true
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "second {}, height {}",
+            self.seconds,
+            calc_score(&self.points)
+        )
+    }
+}
+
+pub fn animate(inp: &str, config: PlayerConfig) -> std::io::Result<()> {
+    Player::new(Sky::new(parse_points(inp)), config).play()
+}
+
+// Draws the converged message with a one pixel border, palette is
+// [background, point]
+pub fn render_message(inp: &str, palette: [Rgb; 2]) -> Raster {
+    let (points, _) = converge_closed_form(&parse_points(inp));
+    let sky = Sky::new(points);
+    let (min_x, min_y, max_x, max_y) = sky.bounds();
+    let width = (max_x - min_x + 3) as usize;
+    let height = (max_y - min_y + 3) as usize;
+
+    let mut raster = Raster::new(width, height, palette[0]);
+    for p in &sky.points {
+        let x = (i64::from(p.x) - min_x + 1) as usize;
+        let y = (i64::from(p.y) - min_y + 1) as usize;
+        raster.set(x, y, palette[1]);
+    }
+    raster
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "position=< 9,  1> velocity=< 0,  2>
+position=< 7,  0> velocity=<-1,  0>
+position=< 3, -2> velocity=<-1,  1>
+position=< 6, 10> velocity=<-2, -1>
+position=< 2, -4> velocity=< 2,  2>
+position=<-6, 10> velocity=< 2, -2>
+position=< 1,  8> velocity=< 1, -1>
+position=< 1,  7> velocity=< 1,  0>
+position=<-3, 11> velocity=< 1, -2>
+position=< 7,  6> velocity=<-1, -1>
+position=<-2,  3> velocity=< 1,  0>
+position=<-4,  3> velocity=< 2,  0>
+position=<10, -3> velocity=<-1,  1>
+position=< 5, 11> velocity=< 1, -2>
+position=< 4,  7> velocity=< 0, -1>
+position=< 8, -2> velocity=< 0,  1>
+position=<15,  0> velocity=<-2,  0>
+position=< 1,  6> velocity=< 1,  0>
+position=< 8,  9> velocity=< 0, -1>
+position=< 3,  3> velocity=<-1,  1>
+position=< 0,  5> velocity=< 0, -1>
+position=<-2,  2> velocity=< 2,  0>
+position=< 5, -2> velocity=< 1,  2>
+position=< 1,  4> velocity=< 2,  1>
+position=<-2,  7> velocity=< 2, -2>
+position=< 3,  6> velocity=<-1, -1>
+position=< 5,  0> velocity=< 1,  0>
+position=<-6,  0> velocity=< 2,  0>
+position=< 5,  9> velocity=< 1, -2>
+position=<14,  7> velocity=<-2,  0>
+position=<-3,  6> velocity=< 2, -1>";
+
+    fn agree(points: &[Point]) -> usize {
+        let (stepped, seconds) = converge(points);
+        let (jumped, t) = converge_closed_form(points);
+        assert_eq!(t, seconds);
+        assert_eq!(Sky::new(jumped).lit, Sky::new(stepped).lit);
+        t
+    }
+
+    #[test]
+    fn example() {
+        let points = parse_points(EXAMPLE);
+        assert_eq!(agree(&points), 3);
+        assert_eq!(calc_score(&converge_closed_form(&points).0), 7);
+    }
+
+    #[test]
+    fn closed_form_matches_stepping() {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+
+        // Points that all land in a 60 x 10 box at some time, flying in
+        // from all directions. With only a few points the smallest extent is
+        // often held for several seconds.
+        for _ in 0..200 {
+            let meet = 1 + rand(5_000) as i32;
+            let points: Vec<Point> = (0..1 + rand(200))
+                .map(|_| {
+                    let (v_x, v_y) = (rand(11) as i32 - 5, rand(3) as i32 - 1);
+                    Point {
+                        x: rand(60) as i32 - v_x * meet,
+                        y: rand(10) as i32 - v_y * meet,
+                        v_x,
+                        v_y,
+                    }
+                })
+                .collect();
+            // Without a spread of vertical speeds the score never rises
+            if points.iter().all(|p| p.v_y == points[0].v_y) {
+                continue;
+            }
+            agree(&points);
+        }
+    }
+}
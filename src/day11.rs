@@ -1,11 +1,19 @@
+use crate::image::{self, Raster, Rgb};
+use crate::sat::{Rect, SizeLimits, SummedAreaTable};
+
 #[aoc_generator(day11)]
 fn build_grid(inp: &str) -> Vec<Vec<i8>> {
     let serial_number: i32 = inp.trim().parse().unwrap();
+    power_grid(serial_number, 300)
+}
+
+// Power levels of a size x size grid of fuel cells, which are numbered from 1
+pub fn power_grid(serial_number: i32, size: usize) -> Vec<Vec<i8>> {
     let mut rows: Vec<Vec<i8>> = Vec::new();
 
-    for y in 1..=300 {
+    for y in 1..=size as i32 {
         let mut row = Vec::new();
-        for x in 1..=300 {
+        for x in 1..=size as i32 {
             let rack_id = x + 10;
             let mut power_level = rack_id * y;
             power_level += serial_number;
@@ -160,61 +168,48 @@ fn transpose(rows: &[Vec<i8>]) -> Vec<Vec<i8>> {
 
 #[aoc(day11, part2, sat)]
 fn part2_sat(rows: &[Vec<i8>]) -> String {
-    let sat = build_sat(&rows);
-    let mut best = std::i64::MIN;
-    let mut best_size = 0;
-    let mut best_coords = (0, 0);
-
-    for y in 1..=300 {
-        for x in 1..=300 {
-            let max_size = 301 - std::cmp::max(x, y);
-            for size in 1..=max_size {
-                let score = get_sat_score(x, y, size, &sat);
-
-                if score > best {
-                    best = score;
-                    best_coords = (x, y);
-                    best_size = size;
-                }
-            }
-        }
-    }
+    let best = SummedAreaTable::new(rows).best_square_any().unwrap();
     println!(
         "Best Score: {} at {:?} sized {}",
-        best, best_coords, best_size
+        best.sum,
+        (best.x + 1, best.y + 1),
+        best.size
     );
-    format!("{},{},{}", best_coords.0, best_coords.1, best_size)
+    format!("{},{},{}", best.x + 1, best.y + 1, best.size)
 }
 
-fn get_sat_score(x: usize, y: usize, size: usize, sat: &[Vec<i64>]) -> i64 {
-    let tl = sat[y - 1][x - 1];
-    let tr = sat[y - 1][x + size - 1];
-    let bl = sat[y + size - 1][x - 1];
-    let br = sat[y + size - 1][x + size - 1];
+#[aoc(day11, part2, sat_parallel)]
+fn part2_sat_parallel(rows: &[Vec<i8>]) -> String {
+    let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let best = SummedAreaTable::new(rows)
+        .best_square_any_parallel(threads)
+        .unwrap();
+    format!("{},{},{}", best.x + 1, best.y + 1, best.size)
+}
 
-    br + tl - tr - bl
+// The best rectangle of fuel cells rather than just squares, with x and y
+// numbered from 1 like the puzzle
+pub fn best_power_rect(serial_number: i32, size: usize, limits: SizeLimits) -> Option<Rect> {
+    let sat = SummedAreaTable::new(&power_grid(serial_number, size));
+    sat.best_rect(limits).map(|r| Rect {
+        x: r.x + 1,
+        y: r.y + 1,
+        ..r
+    })
 }
 
-fn build_sat(rows: &[Vec<i8>]) -> Vec<Vec<i64>> {
-    let mut tab = Vec::new();
+// Blue for -5 through red for 4
+pub fn power_palette() -> [Rgb; 10] {
+    let mut palette = [image::BLACK; 10];
+    palette.copy_from_slice(&image::heat_palette(10));
+    palette
+}
 
-    // Want to put in an empty row/col so the get_sat_score fn is simpler
-    let mut row = Vec::new();
-    for _ in 0..=rows[0].len() {
-        row.push(0);
-    }
-    tab.push(row);
-
-    for y in 0..rows.len() {
-        let mut row = vec![0];
-        let mut row_sum = 0;
-        for x in 0..rows[y].len() {
-            row_sum += i64::from(rows[y][x]);
-            // The prev rows indexing is a bit funky due to the shift
-            let prev_row_sum = tab[y][x + 1];
-            row.push(row_sum + prev_row_sum);
-        }
-        tab.push(row);
-    }
-    tab
+// Map of the fuel cell power levels, with palette[0] for -5 up to palette[9]
+// for 4
+pub fn render_power(inp: &str, palette: [Rgb; 10]) -> Raster {
+    let rows = build_grid(inp);
+    Raster::from_fn(rows[0].len(), rows.len(), |x, y| {
+        palette[(rows[y][x] + 5) as usize]
+    })
 }
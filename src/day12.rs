@@ -1,4 +1,6 @@
+use crate::player::{Animation, Player, PlayerConfig};
 use regex::*;
+use std::collections::HashMap;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 struct Rule {
@@ -85,26 +87,60 @@ fn part1(inp: &InitialState) -> i64 {
     score_state(&state)
 }
 
-#[aoc(day12, part2)]
-fn part2(inp: &InitialState) -> i64 {
-    let rules = &inp.rules;
-    let mut state = inp.state.clone();
-    let mut score = 0;
-    let mut diff = 0;
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Cycle {
+    // generation at which the repeated pattern was first seen
+    pub start: u64,
+    // number of generations before the pattern repeats
+    pub period: u64,
+    // how far first_index moves over one period
+    pub shift: i64,
+}
 
-    let max: i64 = 500_000_000_000;
-    for g in 1..=max {
-        state = iterate_state(&rules, &state);
-        let new_score = score_state(&state);
-        if diff == new_score - score {
-            println!("G {} score: {} diff: {}", g, new_score, diff);
-            return (max - g) * diff + new_score;
+// The cycle the plants from the puzzle input settle into, if the pattern
+// repeats before generation `limit`
+pub fn plant_cycle(inp: &str, limit: u64) -> Option<Cycle> {
+    let inp = parse_input(inp);
+    state_after(&inp.rules, &inp.state, limit).1
+}
+
+// Computes the state after `generations` steps, one at a time until the
+// trimmed pattern repeats (up to a shift in first_index) and then skipping
+// whole cycles. Also returns the cycle, if one turned up on the way.
+fn state_after(rules: &[Rule], initial: &State, generations: u64) -> (State, Option<Cycle>) {
+    let mut seen: HashMap<Vec<bool>, (u64, i64)> = HashMap::new();
+    let mut state = trim_state(initial.clone());
+
+    for g in 0..generations {
+        if let Some(&(start, first_index)) = seen.get(&state.vec) {
+            let cycle = Cycle {
+                start,
+                period: g - start,
+                shift: state.first_index - first_index,
+            };
+            let remaining = generations - g;
+            for _ in 0..remaining % cycle.period {
+                state = iterate_state(rules, &state);
+            }
+            state.first_index += (remaining / cycle.period) as i64 * cycle.shift;
+            return (state, Some(cycle));
         }
-        diff = new_score - score;
-        score = new_score;
+        seen.insert(state.vec.clone(), (g, state.first_index));
+        state = iterate_state(rules, &state);
     }
+    (state, None)
+}
 
-    show_state(&state);
+#[aoc(day12, part2)]
+fn part2(inp: &InitialState) -> i64 {
+    let max: u64 = 50_000_000_000;
+    let (state, cycle) = state_after(&inp.rules, &inp.state, max);
+    if let Some(cycle) = cycle {
+        println!(
+            "Cycle from generation {}: period {}, shift {} per period",
+            cycle.start, cycle.period, cycle.shift
+        );
+    }
     score_state(&state)
 }
 
@@ -179,3 +215,150 @@ fn score_state(state: &State) -> i64 {
         })
         .sum()
 }
+
+#[derive(Debug, Clone)]
+struct Plants<'a> {
+    rules: &'a [Rule],
+    state: State,
+    generation: u64,
+}
+
+impl<'a> Animation for Plants<'a> {
+    fn bounds(&self) -> (i64, i64, i64, i64) {
+        let first = self.state.first_index;
+        (first, 0, first + self.state.vec.len() as i64 - 1, 0)
+    }
+
+    fn cell(&self, x: i64, y: i64) -> char {
+        let i = x - self.state.first_index;
+        if y != 0 || i < 0 {
+            return ' ';
+        }
+        match self.state.vec.get(i as usize) {
+            Some(true) => '#',
+            Some(false) => '.',
+            None => ' ',
+        }
+    }
+
+    fn step(&mut self) -> bool {
+        self.state = iterate_state(self.rules, &self.state);
+        self.generation += 1;
+        true
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "generation {}, score {}",
+            self.generation,
+            score_state(&self.state)
+        )
+    }
+}
+
+pub fn animate(inp: &str, config: PlayerConfig) -> std::io::Result<()> {
+    let initial = parse_input(inp);
+    let plants = Plants {
+        rules: &initial.rules,
+        state: initial.state.clone(),
+        generation: 0,
+    };
+    Player::new(plants, config).play()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(i: u32) -> String {
+        (0..5)
+            .map(|b| if i & (16 >> b) != 0 { '#' } else { '.' })
+            .collect()
+    }
+
+    // Every one of the 32 rules has to be given, so the ones listed make a
+    // plant and the rest leave the pot empty
+    fn input(initial: &str, plants: &[&str]) -> Box<InitialState> {
+        let mut inp = format!("initial state: {}\n\n", initial);
+        for i in 0..32 {
+            let pattern = pattern(i);
+            let output = if plants.contains(&pattern.as_str()) {
+                '#'
+            } else {
+                '.'
+            };
+            inp += &format!("{} => {}\n", pattern, output);
+        }
+        parse_input(&inp)
+    }
+
+    fn example() -> Box<InitialState> {
+        input(
+            "#..#.#..##......###...###",
+            &[
+                "...##", "..#..", ".#...", ".#.#.", ".#.##", ".##..", ".####", "#.#.#", "#.###",
+                "##.#.", "##.##", "###..", "###.#", "####.",
+            ],
+        )
+    }
+
+    fn iterated(inp: &InitialState, generations: u64) -> State {
+        let mut state = trim_state(inp.state.clone());
+        for _ in 0..generations {
+            state = iterate_state(&inp.rules, &state);
+        }
+        state
+    }
+
+    #[test]
+    fn example_after_twenty() {
+        let inp = example();
+        assert_eq!(score_state(&state_after(&inp.rules, &inp.state, 20).0), 325);
+    }
+
+    #[test]
+    fn matches_plain_iteration() {
+        let inp = example();
+        for n in 0..200 {
+            let (state, _) = state_after(&inp.rules, &inp.state, n);
+            assert_eq!(state, iterated(&inp, n), "after {} generations", n);
+        }
+    }
+
+    #[test]
+    fn period_two() {
+        // A single plant becomes a pair, which becomes a single plant one pot
+        // to the right
+        let inp = input("#", &["..#..", ".#...", ".##.."]);
+        let (_, cycle) = state_after(&inp.rules, &inp.state, 3);
+        assert_eq!(
+            cycle,
+            Some(Cycle {
+                start: 0,
+                period: 2,
+                shift: 1,
+            })
+        );
+        for n in 0..50 {
+            let (state, _) = state_after(&inp.rules, &inp.state, n);
+            assert_eq!(state, iterated(&inp, n), "after {} generations", n);
+        }
+        let (state, _) = state_after(&inp.rules, &inp.state, 1_000_001);
+        assert_eq!(score_state(&state), 500_000 + 500_001);
+    }
+
+    #[test]
+    fn growing_pattern_has_no_cycle() {
+        // A plant spreads to both neighbours every generation
+        let spreading: Vec<String> = (0..32)
+            .map(pattern)
+            .filter(|p| p[1..4].contains('#'))
+            .collect();
+        let spreading: Vec<&str> = spreading.iter().map(|p| p.as_str()).collect();
+        let inp = input("#", &spreading);
+        let (state, cycle) = state_after(&inp.rules, &inp.state, 5);
+        assert_eq!(cycle, None);
+        assert_eq!(state, iterated(&inp, 5));
+        assert_eq!(state.vec.iter().filter(|&&p| p).count(), 11);
+    }
+}
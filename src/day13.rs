@@ -1,3 +1,4 @@
+use crate::player::{Animation, Player, PlayerConfig};
 use std::collections::HashMap;
 use std::collections::HashSet;
 
@@ -23,7 +24,7 @@ enum PointType {
     Intersection,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Cart {
     location: Point,
     direction: Direction,
@@ -43,7 +44,7 @@ struct GridPoint {
 struct Grid {
     points: HashMap<Point, GridPoint>,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Carts {
     carts: HashMap<Point, Cart>,
 }
@@ -270,6 +271,92 @@ fn print_grid(grid: &Grid) {
     }
 }
 
+fn show_point(gtype: PointType) -> char {
+    use self::PointType::*;
+    match gtype {
+        Vert => '|',
+        Horiz => '-',
+        ForSlash => '/',
+        BackSlash => '\\',
+        Intersection => '+',
+    }
+}
+
+fn show_cart(direction: Direction) -> char {
+    use self::Direction::*;
+    match direction {
+        Up => '^',
+        Down => 'v',
+        Left => '<',
+        Right => '>',
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Track<'a> {
+    grid: &'a Grid,
+    carts: Carts,
+    crashes: Vec<Point>,
+    ticks: usize,
+}
+
+impl<'a> Animation for Track<'a> {
+    fn bounds(&self) -> (i64, i64, i64, i64) {
+        let max_x = self.grid.points.keys().map(|p| p.x).max().unwrap_or(0);
+        let max_y = self.grid.points.keys().map(|p| p.y).max().unwrap_or(0);
+        (0, 0, max_x as i64, max_y as i64)
+    }
+
+    fn cell(&self, x: i64, y: i64) -> char {
+        if x < 0 || y < 0 {
+            return ' ';
+        }
+        let p = Point {
+            x: x as usize,
+            y: y as usize,
+        };
+        if let Some(cart) = self.carts.carts.get(&p) {
+            show_cart(cart.direction)
+        } else if self.crashes.contains(&p) {
+            'X'
+        } else {
+            self.grid
+                .points
+                .get(&p)
+                .map_or(' ', |gp| show_point(gp.gtype))
+        }
+    }
+
+    fn step(&mut self) -> bool {
+        if self.carts.carts.len() <= 1 {
+            return false;
+        }
+        self.carts = tick2_with_crashes(self.grid, &self.carts, &mut self.crashes);
+        self.ticks += 1;
+        true
+    }
+
+    fn status(&self) -> String {
+        format!(
+            "tick {}, {} carts left, {} crashes",
+            self.ticks,
+            self.carts.carts.len(),
+            self.crashes.len()
+        )
+    }
+}
+
+pub fn animate(inp: &str, config: PlayerConfig) -> std::io::Result<()> {
+    let (grid, carts) = *parse_input(inp.as_bytes());
+    let track = Track {
+        grid: &grid,
+        carts,
+        crashes: Vec::new(),
+        ticks: 0,
+    };
+    Player::new(track, config).play()
+}
+
 fn step_cart(grid: &Grid, cart: &Cart) -> Cart {
     let next_point = next_point(cart.direction, &cart.location);
     let next_gp = &grid.points[&next_point];
@@ -308,6 +395,10 @@ fn tick(grid: &Grid, carts: &Carts) -> Result<Carts, Point> {
 }
 
 fn tick2(grid: &Grid, carts: &Carts) -> Carts {
+    tick2_with_crashes(grid, carts, &mut Vec::new())
+}
+
+fn tick2_with_crashes(grid: &Grid, carts: &Carts, crashes: &mut Vec<Point>) -> Carts {
     let mut ticked_carts = HashMap::with_capacity(carts.carts.len());
     let mut cart_keys = carts.carts.keys().cloned().collect::<Vec<Point>>();
     cart_keys.sort();
@@ -324,11 +415,13 @@ fn tick2(grid: &Grid, carts: &Carts) -> Carts {
         // that hasn't been moved yet
         if next_loc > *cart_id && cart_keys.contains(&next_loc) && !skip_carts.contains(&next_loc) {
             skip_carts.insert(next_loc);
+            crashes.push(next_loc);
             continue;
         }
         // There's a crash if we try to put two carts in the same place
         if ticked_carts.insert(next_loc, next_cart).is_some() {
             ticked_carts.remove(&next_loc);
+            crashes.push(next_loc);
             continue;
         }
     }
@@ -1,87 +1,336 @@
+use std::collections::VecDeque;
+
 type Score = u8;
-type Board = Vec<Score>;
-type Elves = Vec<usize>;
 
 #[aoc(day14, part1)]
 pub fn part1(inp: &str) -> String {
-    let mut board = vec![3, 7];
-    let mut elves = vec![0, 1];
-
     let max: usize = inp.trim().parse().unwrap();
 
-    while board.len() < max + 10 {
-        let cur_scores = elf_scores(&board, &elves);
-        let mut new_recipes = find_new_recipes(&cur_scores);
-        add_new_recipes(&mut board, &mut new_recipes);
-        elves = cycle_elves(board.len(), &elves, &cur_scores);
-    }
-    println!("{:?}", &board[max..(max + 10)]);
-    board[max..(max + 10)]
-        .iter()
-        .map(|n| std::char::from_digit(u32::from(*n), 10).unwrap())
+    Scoreboard::puzzle()
+        .skip(max)
+        .take(10)
+        .map(|n| std::char::from_digit(u32::from(n), 10).unwrap())
         .collect()
 }
 
 #[aoc(day14, part2)]
 pub fn part2(inp: &str) -> usize {
-    let mut board = vec![3, 7];
-    let mut elves = vec![0, 1];
-    let mut up_to = 0;
-
-    let needle: Vec<u8> = inp
+    let needle: Vec<Score> = inp
         .trim()
         .chars()
-        .map(|c| c.to_digit(10).unwrap() as u8)
+        .map(|c| c.to_digit(10).unwrap() as Score)
         .collect();
 
-    loop {
-        let cur_scores = elf_scores(&board, &elves);
-        let mut new_recipes = find_new_recipes(&cur_scores);
-        add_new_recipes(&mut board, &mut new_recipes);
-        elves = cycle_elves(board.len(), &elves, &cur_scores);
+    occurrences(Scoreboard::puzzle(), &[needle])
+        .next()
+        .unwrap()
+        .start
+}
 
-        for (i, window) in board[up_to..].windows(needle.len()).enumerate() {
-            if *window == needle[..] {
-                println!("Found!: {:?}", i + up_to);
-                return i + up_to;
+// Every recipe score in order, made as they're asked for. Each step the elves
+// add the sum of their current recipes' scores as new recipes, one per digit,
+// then each moves forward 1 + its recipe's score.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Scoreboard {
+    board: Vec<Score>,
+    elves: Vec<usize>,
+    base: usize,
+    next: usize,
+}
+
+impl Scoreboard {
+    // The elves start on the first `elves` recipes
+    pub fn new(initial: &[Score], elves: usize) -> Scoreboard {
+        assert!(
+            elves <= initial.len(),
+            "every elf needs a recipe to start on"
+        );
+        Scoreboard {
+            board: initial.to_vec(),
+            elves: (0..elves).collect(),
+            base: 10,
+            next: 0,
+        }
+    }
+
+    pub fn puzzle() -> Scoreboard {
+        Scoreboard::new(&[3, 7], 2)
+    }
+
+    // New recipes are the digits of the sum in this base
+    pub fn with_base(mut self, base: usize) -> Scoreboard {
+        assert!((2..=256).contains(&base), "digits have to fit a Score");
+        self.base = base;
+        self
+    }
+
+    // All the recipes made so far
+    pub fn board(&self) -> &[Score] {
+        &self.board
+    }
+
+    fn step(&mut self) {
+        let mut sum: usize = self.elves.iter().map(|&e| usize::from(self.board[e])).sum();
+        let start = self.board.len();
+        loop {
+            self.board.push((sum % self.base) as Score);
+            sum /= self.base;
+            if sum == 0 {
+                break;
             }
         }
-        up_to = std::cmp::max(board.len() as isize - 5, 0) as usize;
+        self.board[start..].reverse();
+
+        let len = self.board.len();
+        for elf in self.elves.iter_mut() {
+            *elf = (*elf + 1 + usize::from(self.board[*elf])) % len;
+        }
     }
 }
 
-fn elf_scores(board: &Board, elves: &Elves) -> Vec<Score> {
-    let mut res = Vec::new();
-    for &elf in elves {
-        res.push(board[elf]);
+impl Iterator for Scoreboard {
+    type Item = Score;
+
+    fn next(&mut self) -> Option<Score> {
+        if self.next == self.board.len() {
+            if self.elves.is_empty() {
+                return None;
+            }
+            self.step();
+        }
+        self.next += 1;
+        Some(self.board[self.next - 1])
     }
-    res
 }
 
-fn find_new_recipes(inp: &[Score]) -> Vec<Score> {
-    let mut sum: usize = inp.iter().map(|n| *n as usize).sum();
-    if sum == 0 {
-        return vec![0];
+// Aho-Corasick automaton for finding several digit strings at once in a
+// stream, fed one digit at a time
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Matcher {
+    // Full transition table, so feeding a digit is a single lookup
+    delta: Vec<Vec<usize>>,
+    // The needles ending at each state, including through fail links
+    outputs: Vec<Vec<usize>>,
+    lengths: Vec<usize>,
+    state: usize,
+    position: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Occurrence {
+    // Index into the needles
+    pub needle: usize,
+    // Number of digits before the match
+    pub start: usize,
+}
+
+impl Matcher {
+    pub fn new(needles: &[Vec<Score>]) -> Matcher {
+        assert!(needles.iter().all(|n| !n.is_empty()), "empty needle");
+        let alphabet = needles
+            .iter()
+            .flatten()
+            .map(|&d| usize::from(d) + 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut trie: Vec<Vec<Option<usize>>> = vec![vec![None; alphabet]];
+        let mut outputs: Vec<Vec<usize>> = vec![Vec::new()];
+        for (i, needle) in needles.iter().enumerate() {
+            let mut s = 0;
+            for &d in needle {
+                s = match trie[s][usize::from(d)] {
+                    Some(next) => next,
+                    None => {
+                        trie.push(vec![None; alphabet]);
+                        outputs.push(Vec::new());
+                        trie[s][usize::from(d)] = Some(trie.len() - 1);
+                        trie.len() - 1
+                    }
+                };
+            }
+            outputs[s].push(i);
+        }
+
+        // Breadth first so every fail link points at a state that's already
+        // been finished
+        let mut delta = vec![vec![0; alphabet]; trie.len()];
+        let mut fail = vec![0; trie.len()];
+        let mut queue = VecDeque::new();
+        for d in 0..alphabet {
+            if let Some(t) = trie[0][d] {
+                delta[0][d] = t;
+                queue.push_back(t);
+            }
+        }
+        while let Some(s) = queue.pop_front() {
+            let inherited = outputs[fail[s]].clone();
+            outputs[s].extend(inherited);
+            for d in 0..alphabet {
+                match trie[s][d] {
+                    Some(t) => {
+                        fail[t] = delta[fail[s]][d];
+                        delta[s][d] = t;
+                        queue.push_back(t);
+                    }
+                    None => delta[s][d] = delta[fail[s]][d],
+                }
+            }
+        }
+
+        Matcher {
+            delta,
+            outputs,
+            lengths: needles.iter().map(|n| n.len()).collect(),
+            state: 0,
+            position: 0,
+        }
     }
-    let mut res = Vec::new();
-    while sum > 0 {
-        let rem = (sum % 10) as Score;
-        let quot = sum / 10;
-        res.push(rem);
-        sum = quot;
+
+    // Feeds the next digit, returning the needles that end with it
+    pub fn feed(&mut self, digit: Score) -> &[usize] {
+        // Digits that aren't in any needle always go back to the start
+        self.state = self.delta[self.state]
+            .get(usize::from(digit))
+            .cloned()
+            .unwrap_or(0);
+        self.position += 1;
+        &self.outputs[self.state]
+    }
+
+    // How many digits have been fed
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn start_of(&self, needle: usize) -> usize {
+        self.position - self.lengths[needle]
     }
-    res.reverse();
-    res
 }
 
-fn add_new_recipes(board: &mut Board, new_recipes: &mut Vec<Score>) {
-    board.append(new_recipes);
+// Every occurrence of every needle in the digits, ordered by where they end
+// and then longest first
+pub struct Occurrences<I> {
+    digits: I,
+    matcher: Matcher,
+    // Outputs of the current state still to report
+    pending: usize,
 }
 
-fn cycle_elves(board_len: usize, elves: &Elves, scores: &[Score]) -> Elves {
-    elves
-        .iter()
-        .zip(scores.iter())
-        .map(|(elf, score)| (elf + 1 + *score as usize) % board_len)
-        .collect()
+pub fn occurrences<I: Iterator<Item = Score>>(digits: I, needles: &[Vec<Score>]) -> Occurrences<I> {
+    Occurrences {
+        digits,
+        matcher: Matcher::new(needles),
+        pending: 0,
+    }
+}
+
+impl<I: Iterator<Item = Score>> Iterator for Occurrences<I> {
+    type Item = Occurrence;
+
+    fn next(&mut self) -> Option<Occurrence> {
+        while self.pending == 0 {
+            self.pending = self.matcher.feed(self.digits.next()?).len();
+        }
+        let outputs = &self.matcher.outputs[self.matcher.state];
+        let needle = outputs[outputs.len() - self.pending];
+        self.pending -= 1;
+        Some(Occurrence {
+            needle,
+            start: self.matcher.start_of(needle),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(digits: &[Score], needles: &[Vec<Score>]) -> Vec<(usize, usize)> {
+        occurrences(digits.iter().cloned(), needles)
+            .map(|o| (o.needle, o.start))
+            .collect()
+    }
+
+    #[test]
+    fn examples() {
+        for &(after, next) in &[
+            ("9", "5158916779"),
+            ("5", "0124515891"),
+            ("18", "9251071085"),
+            ("2018", "5941429882"),
+        ] {
+            assert_eq!(part1(after), next);
+        }
+        for &(needle, before) in &[("51589", 9), ("01245", 5), ("92510", 18), ("59414", 2018)] {
+            assert_eq!(part2(needle), before);
+        }
+    }
+
+    #[test]
+    fn overlapping_needles() {
+        assert_eq!(
+            found(&[1, 1, 1, 1], &[vec![1, 1]]),
+            vec![(0, 0), (0, 1), (0, 2)]
+        );
+        assert_eq!(
+            found(&[1, 2, 1, 2, 1], &[vec![1, 2, 1]]),
+            vec![(0, 0), (0, 2)]
+        );
+    }
+
+    #[test]
+    fn nested_needles_end_then_longest_first() {
+        // 2 ends first, then 1 2 3 and 2 3 end on the same digit
+        let needles = [vec![2, 3], vec![2], vec![1, 2, 3]];
+        assert_eq!(
+            found(&[0, 1, 2, 3, 9, 2], &needles),
+            vec![(1, 2), (2, 1), (0, 2), (1, 5)]
+        );
+    }
+
+    #[test]
+    fn matcher_positions() {
+        let mut matcher = Matcher::new(&[vec![5, 1], vec![1]]);
+        assert!(matcher.feed(5).is_empty());
+        assert_eq!(matcher.feed(1), &[0, 1]);
+        assert_eq!((matcher.start_of(0), matcher.start_of(1)), (0, 1));
+        // Digits outside every needle just start the search again
+        assert!(matcher.feed(200).is_empty());
+        assert_eq!(matcher.feed(1), &[1]);
+        assert_eq!(matcher.position(), 4);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        for _ in 0..200 {
+            // Few digits so the needles overlap, repeat and nest a lot
+            let needles: Vec<Vec<Score>> = (0..1 + rand(5))
+                .map(|_| (0..1 + rand(4)).map(|_| rand(3) as Score).collect())
+                .collect();
+            let digits: Vec<Score> = (0..rand(60)).map(|_| rand(4) as Score).collect();
+
+            let mut expected = Vec::new();
+            for (i, needle) in needles.iter().enumerate() {
+                for start in 0..digits.len() {
+                    if digits[start..].starts_with(needle) {
+                        expected.push((i, start));
+                    }
+                }
+            }
+            // Same end, longest first, then in the order the needles were given
+            expected.sort_by_key(|&(i, start)| {
+                let len = needles[i].len();
+                (start + len, std::cmp::Reverse(len), i)
+            });
+            assert_eq!(found(&digits, &needles), expected, "{:?}", needles);
+        }
+    }
 }
@@ -1,3 +1,5 @@
+use crate::image::{Raster, Rgb};
+use crate::player::{Animation, Player, PlayerConfig};
 use std::collections::hash_map::{DefaultHasher, HashMap};
 use std::hash::{Hash, Hasher};
 type State = Vec<Vec<CellTy>>;
@@ -164,17 +166,75 @@ fn score_state(state: &State) -> usize {
     tr_count * ly_count
 }
 
-fn show_state(state: &State) {
+fn show_cell(cell: CellTy) -> char {
     use self::CellTy::*;
+    match cell {
+        Open => '.',
+        Trees => '|',
+        Lumberyard => '#',
+    }
+}
+
+fn show_state(state: &State) {
     for l in state.iter() {
-        for c in l.iter() {
-            let show = match c {
-                Open => ".",
-                Trees => "|",
-                Lumberyard => "#",
-            };
-            print!("{}", show);
+        println!("{}", l.iter().map(|c| show_cell(*c)).collect::<String>());
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Forest {
+    state: State,
+    minute: usize,
+}
+
+impl Animation for Forest {
+    fn bounds(&self) -> (i64, i64, i64, i64) {
+        let width = self.state.first().map_or(0, |l| l.len());
+        (0, 0, width as i64 - 1, self.state.len() as i64 - 1)
+    }
+
+    fn cell(&self, x: i64, y: i64) -> char {
+        if x < 0 || y < 0 {
+            return ' ';
         }
-        println!();
+        self.state
+            .get(y as usize)
+            .and_then(|l| l.get(x as usize))
+            .map_or(' ', |c| show_cell(*c))
+    }
+
+    fn step(&mut self) -> bool {
+        self.state = step_state(&self.state);
+        self.minute += 1;
+        true
+    }
+
+    fn status(&self) -> String {
+        format!("minute {}, value {}", self.minute, score_state(&self.state))
+    }
+}
+
+pub fn animate(inp: &str, config: PlayerConfig) -> std::io::Result<()> {
+    let forest = Forest {
+        state: parse(inp),
+        minute: 0,
+    };
+    Player::new(forest, config).play()
+}
+
+// Open, trees, lumberyard
+pub const FOREST_PALETTE: [Rgb; 3] = [[222, 205, 160], [34, 120, 50], [110, 70, 30]];
+
+pub fn render_generation(inp: &str, minutes: usize, palette: [Rgb; 3]) -> Raster {
+    use self::CellTy::*;
+    let mut state = parse(inp);
+    for _ in 0..minutes {
+        state = step_state(&state);
     }
+    let width = state.first().map_or(0, |l| l.len());
+    Raster::from_fn(width, state.len(), |x, y| match state[y][x] {
+        Open => palette[0],
+        Trees => palette[1],
+        Lumberyard => palette[2],
+    })
 }
@@ -1,4 +1,6 @@
+use crate::image::{self, Raster, Rgb};
 use regex::*;
+use std::io::{self, Write};
 
 #[derive(Debug, PartialEq)]
 pub struct Claim {
@@ -158,3 +160,432 @@ pub fn find_unused(claims: &[Claim]) -> u32 {
     }
     unreachable!();
 }
+
+// Unclaimed fabric, then one colour per number of claims
+pub fn overlap_palette(max_claims: usize) -> Vec<Rgb> {
+    let mut palette = vec![image::BLACK];
+    palette.extend(image::heat_palette(max_claims));
+    palette
+}
+
+// Draws the claims' bounding box with a square claimed n times in palette[n],
+// or the last colour if it's claimed more often than that. The counts come
+// from the compressed Overlaps, and when the box is more than max_side square
+// inches across each pixel samples one square inch out of a block, so far
+// apart claims don't need a huge image.
+pub fn render_overlaps(inp: &str, palette: &[Rgb], max_side: usize) -> Raster {
+    assert!(!palette.is_empty(), "need a colour for unclaimed fabric");
+    let overlaps = Overlaps::new(&to_claims(inp));
+    let (x0, y0, x1, y1) = match overlaps.bounds() {
+        Some(bounds) => bounds,
+        None => return Raster::new(0, 0, palette[0]),
+    };
+    let side = std::cmp::max(x1 - x0, y1 - y0);
+    let step = std::cmp::max(side.div_ceil(std::cmp::max(max_side, 1) as u64), 1);
+    let width = (x1 - x0).div_ceil(step) as usize;
+    let height = (y1 - y0).div_ceil(step) as usize;
+
+    Raster::from_fn(width, height, |x, y| {
+        let claims = overlaps.claims_at(x0 + x as u64 * step, y0 + y as u64 * step);
+        palette[std::cmp::min(claims as usize, palette.len() - 1)]
+    })
+}
+
+// Overlap counts over the fabric with the claim edges compressed down to
+// indices, so memory depends on the number of claims rather than how big or
+// spread out they are.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Overlaps {
+    xs: Vec<u64>,
+    ys: Vec<u64>,
+    // Claims sharing each compressed cell, row by row
+    counts: Vec<u32>,
+    // Each claim's id and its compressed x0, x1, y0, y1
+    rects: Vec<(u32, usize, usize, usize, usize)>,
+}
+
+fn compress(mut edges: Vec<u64>) -> Vec<u64> {
+    edges.sort();
+    edges.dedup();
+    edges
+}
+
+impl Overlaps {
+    pub fn new(claims: &[Claim]) -> Overlaps {
+        let xs = compress(
+            claims
+                .iter()
+                .flat_map(|c| vec![u64::from(c.x), u64::from(c.x) + u64::from(c.w)])
+                .collect(),
+        );
+        let ys = compress(
+            claims
+                .iter()
+                .flat_map(|c| vec![u64::from(c.y), u64::from(c.y) + u64::from(c.h)])
+                .collect(),
+        );
+        let index = |edges: &[u64], v: u64| edges.binary_search(&v).unwrap();
+        let rects: Vec<_> = claims
+            .iter()
+            .map(|c| {
+                (
+                    c.id,
+                    index(&xs, u64::from(c.x)),
+                    index(&xs, u64::from(c.x) + u64::from(c.w)),
+                    index(&ys, u64::from(c.y)),
+                    index(&ys, u64::from(c.y) + u64::from(c.h)),
+                )
+            })
+            .collect();
+
+        // Mark the corners of each claim and sweep down then across to fill
+        // them in
+        let width = xs.len();
+        let mut diff = vec![0i64; width * ys.len()];
+        for &(_, x0, x1, y0, y1) in &rects {
+            diff[y0 * width + x0] += 1;
+            diff[y0 * width + x1] -= 1;
+            diff[y1 * width + x0] -= 1;
+            diff[y1 * width + x1] += 1;
+        }
+        for y in 0..ys.len() {
+            for x in 0..width {
+                let above = if y > 0 { diff[(y - 1) * width + x] } else { 0 };
+                let left = if x > 0 { diff[y * width + x - 1] } else { 0 };
+                let diag = if x > 0 && y > 0 {
+                    diff[(y - 1) * width + x - 1]
+                } else {
+                    0
+                };
+                diff[y * width + x] += above + left - diag;
+            }
+        }
+
+        Overlaps {
+            xs,
+            ys,
+            counts: diff.into_iter().map(|c| c as u32).collect(),
+            rects,
+        }
+    }
+
+    // Left, top, right and bottom edges around every claim
+    pub fn bounds(&self) -> Option<(u64, u64, u64, u64)> {
+        Some((
+            *self.xs.first()?,
+            *self.ys.first()?,
+            *self.xs.last()?,
+            *self.ys.last()?,
+        ))
+    }
+
+    // How many claims cover the square inch with its top left corner at x, y
+    pub fn claims_at(&self, x: u64, y: u64) -> u32 {
+        // Index of the compressed cell starting at or before the point
+        let cell = |edges: &[u64], v: u64| edges.partition_point(|&e| e <= v).checked_sub(1);
+        match (cell(&self.xs, x), cell(&self.ys, y)) {
+            (Some(i), Some(j)) => self.counts[j * self.xs.len() + i],
+            _ => 0,
+        }
+    }
+
+    fn cell_area(&self, x: usize, y: usize) -> u64 {
+        (self.xs[x + 1] - self.xs[x]) * (self.ys[y + 1] - self.ys[y])
+    }
+
+    // Square inches claimed by k or more claims
+    pub fn area_at_least(&self, k: u32) -> u64 {
+        let width = self.xs.len();
+        let mut area = 0;
+        for y in 0..self.ys.len().saturating_sub(1) {
+            for x in 0..width.saturating_sub(1) {
+                if self.counts[y * width + x] >= k {
+                    area += self.cell_area(x, y);
+                }
+            }
+        }
+        area
+    }
+
+    pub fn overlapped_area(&self) -> u64 {
+        self.area_at_least(2)
+    }
+
+    // Square inches of each claim (in the order given) that some other claim
+    // also covers
+    pub fn overlapped_area_per_claim(&self) -> Vec<u64> {
+        let width = self.xs.len();
+        self.rects
+            .iter()
+            .map(|&(_, x0, x1, y0, y1)| {
+                (y0..y1)
+                    .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+                    .filter(|(x, y)| self.counts[y * width + x] >= 2)
+                    .map(|(x, y)| self.cell_area(x, y))
+                    .sum()
+            })
+            .collect()
+    }
+
+    // Ids of the claims that don't share a square inch with any other
+    pub fn intact_claims(&self) -> Vec<u32> {
+        self.rects
+            .iter()
+            .filter(|&&(_, x0, x1, y0, y1)| {
+                (y0..y1).all(|y| (x0..x1).all(|x| self.counts[y * self.xs.len() + x] == 1))
+            })
+            .map(|r| r.0)
+            .collect()
+    }
+}
+
+#[aoc(day3, part1, sweep)]
+pub fn count_overlaps_sweep(inp: &str) -> u64 {
+    Overlaps::new(&to_claims(inp)).overlapped_area()
+}
+
+#[aoc(day3, part2, sweep)]
+pub fn find_unused_sweep(claims: &[Claim]) -> u32 {
+    Overlaps::new(claims).intact_claims()[0]
+}
+
+// Claims with no width or height don't cover any fabric, so can't overlap
+fn claims_overlap(a: &Claim, b: &Claim) -> bool {
+    if a.w == 0 || a.h == 0 || b.w == 0 || b.h == 0 {
+        return false;
+    }
+    let (ax1, ay1) = (
+        u64::from(a.x) + u64::from(a.w),
+        u64::from(a.y) + u64::from(a.h),
+    );
+    let (bx1, by1) = (
+        u64::from(b.x) + u64::from(b.w),
+        u64::from(b.y) + u64::from(b.h),
+    );
+    u64::from(a.x) < bx1 && u64::from(b.x) < ax1 && u64::from(a.y) < by1 && u64::from(b.y) < ay1
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ClaimConflicts {
+    pub id: u32,
+    // Ids of every claim sharing at least one square inch with this one
+    pub conflicts: Vec<u32>,
+    pub overlapped_area: u64,
+    // Index into ConflictReport::clusters
+    pub cluster: usize,
+}
+
+// Which claims get in each other's way. Clusters are the connected components
+// of the overlap graph, largest first, so a claim that overlaps nothing is a
+// cluster of one.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConflictReport {
+    pub claims: Vec<ClaimConflicts>,
+    pub clusters: Vec<Vec<u32>>,
+}
+
+impl ConflictReport {
+    pub fn new(claims: &[Claim]) -> ConflictReport {
+        // Sort by left edge so each claim only has to be checked against the
+        // ones starting before it ends
+        let mut order: Vec<usize> = (0..claims.len()).collect();
+        order.sort_by_key(|&i| claims[i].x);
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); claims.len()];
+        for (n, &i) in order.iter().enumerate() {
+            let right = u64::from(claims[i].x) + u64::from(claims[i].w);
+            for &j in order[n + 1..]
+                .iter()
+                .take_while(|&&j| u64::from(claims[j].x) < right)
+            {
+                if claims_overlap(&claims[i], &claims[j]) {
+                    edges[i].push(j);
+                    edges[j].push(i);
+                }
+            }
+        }
+
+        let mut component = vec![usize::MAX; claims.len()];
+        let mut clusters: Vec<Vec<usize>> = Vec::new();
+        for start in 0..claims.len() {
+            if component[start] != usize::MAX {
+                continue;
+            }
+            let mut members = vec![start];
+            component[start] = clusters.len();
+            let mut next = 0;
+            while next < members.len() {
+                for &j in &edges[members[next]] {
+                    if component[j] == usize::MAX {
+                        component[j] = clusters.len();
+                        members.push(j);
+                    }
+                }
+                next += 1;
+            }
+            clusters.push(members);
+        }
+
+        // Renumber the clusters biggest first, keeping input order for ties
+        let mut by_size: Vec<usize> = (0..clusters.len()).collect();
+        by_size.sort_by_key(|&c| std::cmp::Reverse(clusters[c].len()));
+        let mut rank = vec![0; clusters.len()];
+        for (r, &c) in by_size.iter().enumerate() {
+            rank[c] = r;
+        }
+
+        let areas = Overlaps::new(claims).overlapped_area_per_claim();
+        let report_claims = claims
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let mut conflicts: Vec<u32> = edges[i].iter().map(|&j| claims[j].id).collect();
+                conflicts.sort();
+                ClaimConflicts {
+                    id: c.id,
+                    conflicts,
+                    overlapped_area: areas[i],
+                    cluster: rank[component[i]],
+                }
+            })
+            .collect();
+        let clusters = by_size
+            .into_iter()
+            .map(|c| {
+                let mut ids: Vec<u32> = clusters[c].iter().map(|&i| claims[i].id).collect();
+                ids.sort();
+                ids
+            })
+            .collect();
+
+        ConflictReport {
+            claims: report_claims,
+            clusters,
+        }
+    }
+
+    pub fn largest_cluster(&self) -> &[u32] {
+        self.clusters.first().map_or(&[], |c| c.as_slice())
+    }
+
+    // One row per claim, with its conflicts separated by spaces
+    pub fn write_csv(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "id,cluster,cluster_size,overlapped_area,conflicts")?;
+        for c in &self.claims {
+            let conflicts: Vec<String> = c.conflicts.iter().map(|id| id.to_string()).collect();
+            writeln!(
+                out,
+                "{},{},{},{},{}",
+                c.id,
+                c.cluster,
+                self.clusters[c.cluster].len(),
+                c.overlapped_area,
+                conflicts.join(" ")
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_text(&self, out: &mut impl Write) -> io::Result<()> {
+        let intact = self
+            .claims
+            .iter()
+            .filter(|c| c.conflicts.is_empty())
+            .count();
+        writeln!(
+            out,
+            "{} claims in {} clusters, {} intact",
+            self.claims.len(),
+            self.clusters.len(),
+            intact
+        )?;
+        writeln!(
+            out,
+            "Largest cluster: {} claims",
+            self.largest_cluster().len()
+        )?;
+        for c in &self.claims {
+            writeln!(
+                out,
+                "#{}: cluster {}, {} sq in overlapped, conflicts with {:?}",
+                c.id, c.cluster, c.overlapped_area, c.conflicts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "#1 @ 1,3: 4x4\n#2 @ 3,1: 4x4\n#3 @ 5,5: 2x2";
+
+    #[test]
+    fn renders_the_bounding_box() {
+        let palette = overlap_palette(2);
+        let raster = render_overlaps(EXAMPLE, &palette, 100);
+        assert_eq!((raster.width, raster.height), (6, 6));
+        // Square inch (1, 1) isn't claimed, (3, 3) is claimed twice and (5, 5)
+        // only by #3
+        assert_eq!(raster.get(0, 0), palette[0]);
+        assert_eq!(raster.get(2, 2), palette[2]);
+        assert_eq!(raster.get(4, 4), palette[1]);
+    }
+
+    #[test]
+    fn renders_sparse_claims_sampled() {
+        let inp = "#1 @ 0,0: 10x10\n#2 @ 4000000000,5: 3x3\n#3 @ 5,5: 10x10";
+        let palette = overlap_palette(2);
+        let raster = render_overlaps(inp, &palette, 100);
+        assert!(raster.width <= 100 && raster.height <= 100);
+        assert_eq!(raster.get(0, 0), palette[1]);
+        assert_eq!(raster.get(raster.width - 1, raster.height - 1), palette[0]);
+        // Counts past the end of the palette use its last colour
+        let raster = render_overlaps(EXAMPLE, &palette[..2], 100);
+        assert_eq!(raster.get(2, 2), palette[1]);
+    }
+
+    fn conflict_free(claims: &[Claim]) -> Vec<u32> {
+        ConflictReport::new(claims)
+            .claims
+            .iter()
+            .filter(|c| c.conflicts.is_empty())
+            .map(|c| c.id)
+            .collect()
+    }
+
+    #[test]
+    fn empty_claims_overlap_nothing() {
+        let claims = to_claims("#1 @ 1,1: 4x4\n#2 @ 2,2: 0x3\n#3 @ 2,2: 2x0\n#4 @ 3,3: 1x1");
+        assert_eq!(conflict_free(&claims), vec![2, 3]);
+        assert_eq!(Overlaps::new(&claims).intact_claims(), vec![2, 3]);
+    }
+
+    #[test]
+    fn conflicts_agree_with_intact_claims() {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n) as u32
+        };
+        for _ in 0..200 {
+            let claims: Vec<Claim> = (1..=1 + rand(8))
+                .map(|id| Claim {
+                    id,
+                    x: rand(20),
+                    y: rand(20),
+                    w: rand(6),
+                    h: rand(6),
+                })
+                .collect();
+            assert_eq!(
+                conflict_free(&claims),
+                Overlaps::new(&claims).intact_claims(),
+                "{:?}",
+                claims
+            );
+        }
+    }
+}
@@ -1,40 +1,140 @@
+use std::io::{self, Read};
+
 // All ASCII all the time!
 #[aoc(day5, part1)]
 fn part1(nums: &[u8]) -> usize {
-    dedup(nums, 0).len()
+    Reactor::puzzle().reduce(nums.iter().cloned(), &[]).len()
 }
 
 #[aoc(day5, part2)]
 fn part2(inp: &[u8]) -> usize {
-    (b'A'..b'Z')
-        .map(|bad| dedup(inp, bad).len())
+    Reactor::puzzle()
+        .removal_table(inp, b'A'..=b'Z')
+        .into_iter()
+        .map(|(_, len)| len)
         .min()
         .unwrap_or(0)
 }
 
-fn dedup(nums: &[u8], bad: u8) -> Vec<u8> {
-    let mut res = Vec::new();
-    for &y in nums.iter().filter(|&x| x & (255 - 32) != bad) {
-        if let Some(&x) = res.last() {
-            // the difference between lowercase and capital ascii
-            // characters is 1 bit. It's almost as though someone
-            // thought it through and made good decisions
-            if (x ^ y) == 32 {
-                res.pop();
-            } else {
-                res.push(y);
+// the difference between lowercase and capital ascii
+// characters is 1 bit. It's almost as though someone
+// thought it through and made good decisions
+pub fn opposite_polarity(x: u8, y: u8) -> bool {
+    (x ^ y) == 32
+}
+
+// Both polarities of a unit type
+pub fn unit_type(unit: u8) -> [u8; 2] {
+    [unit.to_ascii_uppercase(), unit.to_ascii_lowercase()]
+}
+
+// Reduces polymers where adjacent units u, v react (and both disappear)
+// whenever reacts(u, v)
+pub struct Reactor<F> {
+    reacts: F,
+}
+
+impl Reactor<fn(u8, u8) -> bool> {
+    pub fn puzzle() -> Self {
+        Reactor::new(opposite_polarity)
+    }
+}
+
+impl<F: Fn(u8, u8) -> bool> Reactor<F> {
+    pub fn new(reacts: F) -> Reactor<F> {
+        Reactor { reacts }
+    }
+
+    // The fully reacted polymer, after dropping every unit in `removed`
+    pub fn reduce(&self, units: impl IntoIterator<Item = u8>, removed: &[u8]) -> Vec<u8> {
+        let mut res = Vec::new();
+        for y in units.into_iter().filter(|u| !removed.contains(u)) {
+            match res.last() {
+                Some(&x) if (self.reacts)(x, y) => {
+                    res.pop();
+                }
+                _ => res.push(y),
             }
-        } else {
-            res.push(y)
         }
+        res
     }
-    res
+
+    // Same as reduce, reading the polymer a byte at a time and ignoring
+    // whitespace
+    pub fn reduce_reader(&self, reader: impl Read, removed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut err = None;
+        let units = io::BufReader::new(reader)
+            .bytes()
+            .map_while(|b| b.map_err(|e| err = Some(e)).ok())
+            .filter(|b| !b.is_ascii_whitespace());
+        let res = self.reduce(units, removed);
+        match err {
+            Some(e) => Err(e),
+            None => Ok(res),
+        }
+    }
+
+    // Length of the reduced polymer with each candidate unit type (in both
+    // polarities) taken out, in the order given
+    pub fn removal_table(
+        &self,
+        polymer: &[u8],
+        candidates: impl IntoIterator<Item = u8>,
+    ) -> Vec<(u8, usize)> {
+        candidates
+            .into_iter()
+            .map(|unit| {
+                let removed = unit_type(unit);
+                (unit, self.reduce(polymer.iter().cloned(), &removed).len())
+            })
+            .collect()
+    }
+}
+
+// Compare with `cargo aoc bench -d 5 -p 2`
+#[aoc(day5, part2, parallel)]
+fn part2_parallel(inp: &[u8]) -> usize {
+    let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+    removal_table_parallel(inp, threads)
+        .into_iter()
+        .map(|(_, len)| len)
+        .min()
+        .unwrap_or(0)
+}
+
+// The puzzle's removal table for every unit A to Z. Like part2_2 the polymer
+// is reduced once first: taking out a unit type can't stop two units that
+// have already reacted from reacting. The units are then dealt out round
+// robin across the threads.
+pub fn removal_table_parallel(polymer: &[u8], threads: usize) -> Vec<(u8, usize)> {
+    let threads = std::cmp::max(threads, 1);
+    let reactor = Reactor::puzzle();
+    let reduced = reactor.reduce(polymer.iter().cloned(), &[]);
+    let units: Vec<u8> = (b'A'..=b'Z').collect();
+
+    let mut table: Vec<(u8, usize)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let (reactor, reduced, units) = (&reactor, &reduced, &units);
+                scope.spawn(move || {
+                    let mine = units.iter().skip(t).step_by(threads).cloned();
+                    reactor.removal_table(reduced, mine)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+    table.sort();
+    table
 }
 
 #[aoc(day5, part2, maybe_cleaner)]
 fn part2_2(inp: &[u8]) -> usize {
     let cleaned = initial_clean(inp);
-    (b'A'..b'Z')
+    (b'A'..=b'Z')
         .map(|bad| dedup2(&cleaned, bad))
         .min()
         .unwrap_or(0)
@@ -63,3 +163,94 @@ fn dedup2(nums: &[u8], bad: u8) -> usize {
     }
     res.len()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[u8] = b"dabAcCaCBAcCcaDA";
+
+    #[test]
+    fn example() {
+        assert_eq!(
+            Reactor::puzzle().reduce(EXAMPLE.iter().cloned(), &[]),
+            b"dabCBAcaDA"
+        );
+        assert_eq!(part1(EXAMPLE), 10);
+        assert_eq!(part2(EXAMPLE), 4);
+        assert_eq!(
+            Reactor::puzzle().removal_table(EXAMPLE, b'A'..=b'D'),
+            vec![(b'A', 6), (b'B', 8), (b'C', 4), (b'D', 6)]
+        );
+    }
+
+    #[test]
+    fn removing_z_is_best() {
+        let polymer = b"aZAbZB";
+        assert_eq!(part2(polymer), 0);
+        assert_eq!(part2_2(polymer), 0);
+        let table = Reactor::puzzle().removal_table(polymer, b'A'..=b'Z');
+        assert_eq!(table.last(), Some(&(b'Z', 0)));
+        assert!(table[..25].iter().all(|&(_, len)| len > 0));
+    }
+
+    #[test]
+    fn reduce_from_a_reader() {
+        let reactor = Reactor::puzzle();
+        let inp = "dabAcCaC\nBAcCcaDA\n";
+        let reduced = reactor.reduce_reader(inp.as_bytes(), &[]).unwrap();
+        assert_eq!(reduced, b"dabCBAcaDA");
+        let without_c = reactor.reduce_reader(inp.as_bytes(), &unit_type(b'c'));
+        assert_eq!(without_c.unwrap(), b"daDA");
+
+        // Units of the same letter react in this one, whatever their polarity
+        let same_letter = Reactor::new(|x: u8, y: u8| x.eq_ignore_ascii_case(&y));
+        assert_eq!(
+            same_letter.reduce_reader(&b"aAab  Bc"[..], &[]).unwrap(),
+            b"ac"
+        );
+    }
+
+    #[test]
+    fn reader_errors_are_returned() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+        }
+        let polymer = io::Cursor::new(b"aA").chain(Broken);
+        let err = Reactor::puzzle().reduce_reader(polymer, &[]).unwrap_err();
+        assert_eq!(err.to_string(), "broken");
+    }
+
+    #[test]
+    fn parallel_table_matches() {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        let mut polymers = vec![EXAMPLE.to_vec(), b"aZAbZB".to_vec(), Vec::new()];
+        for _ in 0..30 {
+            // A handful of unit types, so plenty of them react
+            let len = rand(300);
+            polymers.push((0..len).map(|_| b"aAbBcCzZ"[rand(8) as usize]).collect());
+        }
+
+        for polymer in &polymers {
+            let expected = Reactor::puzzle().removal_table(polymer, b'A'..=b'Z');
+            for threads in &[0, 1, 2, 3, 8, 26, 40] {
+                assert_eq!(
+                    removal_table_parallel(polymer, *threads),
+                    expected,
+                    "{} threads on {:?}",
+                    threads,
+                    String::from_utf8_lossy(polymer)
+                );
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::image::{self, Raster, Rgb};
 use counter::Counter;
 use std::collections::HashSet;
 
@@ -58,6 +59,24 @@ pub fn part1(points: &[(Id, Coord)]) -> usize {
 #[aoc(day6, part1, inside_out)]
 pub fn part1_io(points: &[(Id, Coord)]) -> usize {
     let (x_max, y_max) = get_bounds(points);
+    let grid = ownership_grid(points, x_max, y_max);
+    let edge_lords = get_edges(&grid, (x_max as i32, y_max as i32));
+    let areas = get_areas(&grid);
+    areas
+        .most_common_ordered()
+        .iter()
+        .filter_map(|(id, count)| {
+            if edge_lords.contains(id) {
+                None
+            } else {
+                Some(*count)
+            }
+        })
+        .next()
+        .unwrap_or(0)
+}
+
+fn ownership_grid(points: &[(Id, Coord)], x_max: usize, y_max: usize) -> Vec<(Option<Id>, usize)> {
     let mut grid = Vec::with_capacity(x_max * y_max as usize);
 
     for y in 0..y_max {
@@ -76,20 +95,7 @@ pub fn part1_io(points: &[(Id, Coord)]) -> usize {
             grid.push((owner, min));
         }
     }
-    let edge_lords = get_edges(&grid, (x_max as i32, y_max as i32));
-    let areas = get_areas(&grid);
-    areas
-        .most_common_ordered()
-        .iter()
-        .filter_map(|(id, count)| {
-            if edge_lords.contains(id) {
-                None
-            } else {
-                Some(*count)
-            }
-        })
-        .next()
-        .unwrap_or(0)
+    grid
 }
 
 #[aoc(day6, part2)]
@@ -161,3 +167,599 @@ fn get_bounds_fold(inp: &[(Id, Coord)]) -> (usize, usize) {
     });
     (x_max as usize, y_max as usize)
 }
+
+// Ties, points
+pub const OWNERSHIP_PALETTE: [Rgb; 2] = [image::WHITE, image::BLACK];
+
+// Colours each cell by its closest point, using the owner's id to pick from
+// `owners`, with ties and the points themselves drawn in the palette colours
+pub fn render_ownership(inp: &str, palette: [Rgb; 2], owners: &[Rgb]) -> Raster {
+    assert!(!owners.is_empty(), "need at least one owner colour");
+    let points = part1_g(inp);
+    let (x_max, y_max) = get_bounds(&points);
+    let grid = ownership_grid(&points, x_max, y_max);
+    Raster::from_fn(x_max, y_max, |x, y| match grid[y * x_max + x] {
+        (Some(_), 0) => palette[1],
+        (Some(id), _) => owners[id % owners.len()],
+        (None, _) => palette[0],
+    })
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Metric {
+    Manhattan,
+    Euclidean,
+    Chebyshev,
+}
+
+impl Metric {
+    // Euclidean distances are left squared so everything stays integral,
+    // only the ordering matters here
+    pub fn distance(self, (x1, y1): Coord, (x2, y2): Coord) -> i64 {
+        let dx = (i64::from(x1) - i64::from(x2)).abs();
+        let dy = (i64::from(y1) - i64::from(y2)).abs();
+        match self {
+            Metric::Manhattan => dx + dy,
+            Metric::Euclidean => dx * dx + dy * dy,
+            Metric::Chebyshev => std::cmp::max(dx, dy),
+        }
+    }
+}
+
+// Inclusive box around all the points, which may well be negative
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Bounds {
+    min: Coord,
+    max: Coord,
+}
+
+fn bounds(points: &[(Id, Coord)]) -> Bounds {
+    use std::cmp::{max, min};
+    let first = points.first().map_or((0, 0), |(_, p)| *p);
+    points.iter().fold(
+        Bounds {
+            min: first,
+            max: first,
+        },
+        |b, (_, (x, y))| Bounds {
+            min: (min(b.min.0, *x), min(b.min.1, *y)),
+            max: (max(b.max.0, *x), max(b.max.1, *y)),
+        },
+    )
+}
+
+// The uniquely closest point to the cell, if there is one
+fn owner(points: &[(Id, Coord)], metric: Metric, cell: Coord) -> Option<Id> {
+    let mut min = i64::MAX;
+    let mut owner = None;
+    for (id, p) in points {
+        let dist = metric.distance(cell, *p);
+        if dist == min {
+            owner = None
+        } else if dist < min {
+            owner = Some(*id);
+            min = dist
+        }
+    }
+    owner
+}
+
+// The ids whose regions contain infinitely many cells.
+//
+// Manhattan: outside the box, moving away along a row or column (or
+// diagonally from a corner) adds the same amount to every point's distance, so
+// the owner of any cell outside the box is the owner of the cell just outside
+// it in that row, column or corner. A region is infinite exactly when it owns
+// part of the ring just outside the box.
+//
+// Chebyshev: far enough out every cell is in one of the four cones (owned by
+// the unique extreme point in that direction) or in a diagonal band y - x = k
+// beyond both edges, where distance grows by one per step for every point. The
+// bands only differ for k between the extremes of y - x, so probing one cell
+// in each of those, plus the cones, covers every direction to infinity.
+//
+// Euclidean: a region is unbounded exactly when its point is on the boundary
+// of the convex hull. Hull vertices win a cone of directions outright, and
+// points on a hull edge win the ray through them perpendicular to the edge.
+pub fn infinite_regions(points: &[(Id, Coord)], metric: Metric) -> HashSet<Id> {
+    match metric {
+        Metric::Manhattan => ring_cells(bounds(points), 1)
+            .into_iter()
+            .filter_map(|c| owner(points, metric, c))
+            .collect(),
+        Metric::Chebyshev => chebyshev_probes(points)
+            .into_iter()
+            .filter_map(|c| owner(points, metric, c))
+            .collect(),
+        Metric::Euclidean => on_hull(points),
+    }
+}
+
+// The cells at Chebyshev distance r outside the box
+fn ring_cells(b: Bounds, r: i32) -> Vec<Coord> {
+    let (x0, y0) = (b.min.0 - r, b.min.1 - r);
+    let (x1, y1) = (b.max.0 + r, b.max.1 + r);
+    let mut res = Vec::new();
+    for x in x0..=x1 {
+        res.push((x, y0));
+        res.push((x, y1));
+    }
+    for y in y0 + 1..y1 {
+        res.push((x0, y));
+        res.push((x1, y));
+    }
+    res
+}
+
+fn chebyshev_probes(points: &[(Id, Coord)]) -> Vec<Coord> {
+    let b = bounds(points);
+    let span = std::cmp::max(b.max.0 - b.min.0, b.max.1 - b.min.1) + 1;
+    let mut res = vec![
+        (b.max.0 + span, b.min.1),
+        (b.min.0 - span, b.min.1),
+        (b.min.0, b.max.1 + span),
+        (b.min.0, b.min.1 - span),
+    ];
+
+    // Work out the bands for the up-right diagonal with the points mirrored
+    // into each quadrant in turn, then mirror the probe back
+    for &(sx, sy) in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+        let (min_x, max_x) = mirrored(b.min.0, b.max.0, sx);
+        let (min_y, max_y) = mirrored(b.min.1, b.max.1, sy);
+        for k in (min_y - max_x)..=(max_y - min_x) {
+            let x = std::cmp::max(max_x, max_y - k);
+            res.push((x * sx, (x + k) * sy));
+        }
+    }
+    res
+}
+
+fn mirrored(lo: i32, hi: i32, sign: i32) -> (i32, i32) {
+    if sign > 0 {
+        (lo, hi)
+    } else {
+        (-hi, -lo)
+    }
+}
+
+fn cross(o: Coord, a: Coord, b: Coord) -> i64 {
+    let (ox, oy) = (i64::from(o.0), i64::from(o.1));
+    (i64::from(a.0) - ox) * (i64::from(b.1) - oy) - (i64::from(a.1) - oy) * (i64::from(b.0) - ox)
+}
+
+// Ids of the points on the convex hull's boundary, including those in the
+// middle of an edge. Points sharing a location never own anything so are left
+// out.
+fn on_hull(points: &[(Id, Coord)]) -> HashSet<Id> {
+    let mut coords: Vec<Coord> = points.iter().map(|(_, p)| *p).collect();
+    coords.sort();
+    let dups: HashSet<Coord> = coords
+        .windows(2)
+        .filter(|w| w[0] == w[1])
+        .map(|w| w[0])
+        .collect();
+    coords.dedup();
+
+    // Andrew's monotone chain, dropping collinear points
+    let mut hull: Vec<Coord> = Vec::new();
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &Coord>> = if pass == 0 {
+            Box::new(coords.iter())
+        } else {
+            Box::new(coords.iter().rev())
+        };
+        for &c in iter {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], c) <= 0
+            {
+                hull.pop();
+            }
+            hull.push(c);
+        }
+        hull.pop();
+    }
+
+    let on_edge = |p: Coord| {
+        if hull.len() < 2 {
+            return true;
+        }
+        (0..hull.len()).any(|i| {
+            let (a, b) = (hull[i], hull[(i + 1) % hull.len()]);
+            cross(a, b, p) == 0
+                && std::cmp::min(a.0, b.0) <= p.0
+                && p.0 <= std::cmp::max(a.0, b.0)
+                && std::cmp::min(a.1, b.1) <= p.1
+                && p.1 <= std::cmp::max(a.1, b.1)
+        })
+    };
+    points
+        .iter()
+        .filter(|(_, p)| !dups.contains(p) && on_edge(*p))
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+// Inclusive box of cells that holds every cell owned by a finite region.
+//
+// Manhattan: by the argument in infinite_regions a cell outside the box has
+// the same owner as one just outside it, which then owns a whole ray, so
+// finite regions stay inside the box.
+//
+// Chebyshev: a corner cell outside the box keeps its owner moving diagonally
+// away, and a cell beyond the right edge by at least the box's height is
+// further right than up or down from every point, so keeps its owner moving
+// right (and the same for the other edges). Widening the box by its height
+// and width covers everything else.
+//
+// Euclidean: a finite Voronoi cell is the convex hull of its vertices, which
+// are the circumcentres of the Delaunay triangles around its point, so it
+// fits in a circle around the point with the largest of those circumradii.
+fn finite_cells_bounds(points: &[(Id, Coord)], metric: Metric, infinite: &HashSet<Id>) -> Bounds {
+    let b = bounds(points);
+    match metric {
+        Metric::Manhattan => b,
+        Metric::Chebyshev => {
+            let (w, h) = (b.max.0 - b.min.0, b.max.1 - b.min.1);
+            Bounds {
+                min: (b.min.0 - h, b.min.1 - w),
+                max: (b.max.0 + h, b.max.1 + w),
+            }
+        }
+        Metric::Euclidean => {
+            let radii = delaunay_radii(points);
+            points
+                .iter()
+                .zip(radii)
+                .filter(|((id, _), _)| !infinite.contains(id))
+                .fold(b, |b, ((_, (x, y)), r)| {
+                    // Round up, and one more for floating point error
+                    let r = r.ceil() as i32 + 1;
+                    Bounds {
+                        min: (std::cmp::min(b.min.0, x - r), std::cmp::min(b.min.1, y - r)),
+                        max: (std::cmp::max(b.max.0, x + r), std::cmp::max(b.max.1, y + r)),
+                    }
+                })
+        }
+    }
+}
+
+// For every point, the largest circumradius of a Delaunay triangle it's a
+// corner of (0 if it's in none). A triangle is Delaunay when no other point is
+// strictly inside its circumcircle. O(n^4), which is fine for puzzle sizes.
+fn delaunay_radii(points: &[(Id, Coord)]) -> Vec<f64> {
+    let n = points.len();
+    let mut res = vec![0.0f64; n];
+    for i in 0..n {
+        for j in i + 1..n {
+            for k in j + 1..n {
+                let (a, b, c) = (points[i].1, points[j].1, points[k].1);
+                let turn = cross(a, b, c);
+                if turn == 0 {
+                    continue;
+                }
+                // in_circle wants the triangle counter clockwise
+                let (b, c) = if turn > 0 { (b, c) } else { (c, b) };
+                if points.iter().any(|(_, d)| in_circle(a, b, c, *d)) {
+                    continue;
+                }
+                let r = circumradius(a, b, c);
+                for &t in &[i, j, k] {
+                    res[t] = res[t].max(r);
+                }
+            }
+        }
+    }
+    res
+}
+
+// Whether d is strictly inside the circumcircle of the counter clockwise
+// triangle a, b, c
+fn in_circle(a: Coord, b: Coord, c: Coord, d: Coord) -> bool {
+    let row = |p: Coord| {
+        let (x, y) = (i128::from(p.0 - d.0), i128::from(p.1 - d.1));
+        (x, y, x * x + y * y)
+    };
+    let (ax, ay, aw) = row(a);
+    let (bx, by, bw) = row(b);
+    let (cx, cy, cw) = row(c);
+    ax * (by * cw - bw * cy) - ay * (bx * cw - bw * cx) + aw * (bx * cy - by * cx) > 0
+}
+
+fn circumradius(a: Coord, b: Coord, c: Coord) -> f64 {
+    let f = |p: Coord| (f64::from(p.0), f64::from(p.1));
+    let ((ax, ay), (bx, by), (cx, cy)) = (f(a), f(b), f(c));
+    let d = 2.0 * (ax * (by - cy) + bx * (cy - ay) + cx * (ay - by));
+    let (a2, b2, c2) = (ax * ax + ay * ay, bx * bx + by * by, cx * cx + cy * cy);
+    let ux = (a2 * (by - cy) + b2 * (cy - ay) + c2 * (ay - by)) / d;
+    let uy = (a2 * (cx - bx) + b2 * (ax - cx) + c2 * (bx - ax)) / d;
+    (ux - ax).hypot(uy - ay)
+}
+
+// The area of every point's region, or None where it's infinite
+pub fn region_areas(points: &[(Id, Coord)], metric: Metric) -> Vec<(Id, Option<usize>)> {
+    let infinite = infinite_regions(points, metric);
+    let b = finite_cells_bounds(points, metric, &infinite);
+    let owners: Vec<Id> = (b.min.0..=b.max.0)
+        .flat_map(|x| (b.min.1..=b.max.1).map(move |y| (x, y)))
+        .filter_map(|c| owner(points, metric, c))
+        .filter(|id| !infinite.contains(id))
+        .collect();
+    let areas: Counter<Id> = owners.into_iter().collect();
+
+    points
+        .iter()
+        .map(|(id, _)| {
+            if infinite.contains(id) {
+                (*id, None)
+            } else {
+                (*id, Some(areas.get(id).cloned().unwrap_or(0)))
+            }
+        })
+        .collect()
+}
+
+pub fn largest_finite_area(points: &[(Id, Coord)], metric: Metric) -> usize {
+    region_areas(points, metric)
+        .into_iter()
+        .filter_map(|(_, area)| area)
+        .max()
+        .unwrap_or(0)
+}
+
+#[aoc(day6, part1, exact)]
+pub fn part1_exact(points: &[(Id, Coord)]) -> usize {
+    largest_finite_area(points, Metric::Manhattan)
+}
+
+// Cells whose total Manhattan distance to every point is under the threshold.
+// The region is convex, so each row of it is a single run of cells.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SafeRegion {
+    pub threshold: i64,
+    // (y, first x, last x) for every row, top to bottom
+    pub rows: Vec<(i32, i32, i32)>,
+}
+
+impl SafeRegion {
+    pub fn size(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|(_, lo, hi)| (hi - lo + 1) as usize)
+            .sum()
+    }
+
+    pub fn contains(&self, (x, y): Coord) -> bool {
+        self.rows
+            .binary_search_by_key(&y, |r| r.0)
+            .map(|i| self.rows[i].1 <= x && x <= self.rows[i].2)
+            .unwrap_or(false)
+    }
+
+    pub fn render(&self, inside: Rgb, outside: Rgb) -> Raster {
+        let min_x = self.rows.iter().map(|r| r.1).min().unwrap_or(0);
+        let max_x = self.rows.iter().map(|r| r.2).max().unwrap_or(-1);
+        let min_y = self.rows.first().map_or(0, |r| r.0);
+        let width = (max_x - min_x + 1) as usize;
+        let mut raster = Raster::new(width, self.rows.len(), outside);
+        for (y, lo, hi) in &self.rows {
+            for x in *lo..=*hi {
+                raster.set((x - min_x) as usize, (y - min_y) as usize, inside);
+            }
+        }
+        raster
+    }
+}
+
+// Sum of |v - c| over all the coords, from the sorted coords and their prefix
+// sums
+fn axis_distance(sorted: &[i64], prefix: &[i64], v: i64) -> i64 {
+    let le = sorted.partition_point(|c| *c <= v);
+    let n = sorted.len();
+    let below = v * le as i64 - prefix[le];
+    let above = (prefix[n] - prefix[le]) - v * (n - le) as i64;
+    below + above
+}
+
+// Every position along one axis whose distance sum is under the limit, as the
+// first position and the sums from there on. The sum is convex, so these are
+// contiguous around the median, and can reach well outside the points.
+fn axis_sums(coords: &[i32], limit: i64) -> (i64, Vec<i64>) {
+    let mut sorted: Vec<i64> = coords.iter().map(|c| i64::from(*c)).collect();
+    sorted.sort();
+    let mut prefix = vec![0];
+    for c in &sorted {
+        prefix.push(prefix[prefix.len() - 1] + c);
+    }
+    let dist = |v| axis_distance(&sorted, &prefix, v);
+
+    let median = sorted.get(sorted.len() / 2).cloned().unwrap_or(0);
+    if dist(median) >= limit {
+        return (median, Vec::new());
+    }
+    let mut lo = median;
+    while dist(lo - 1) < limit {
+        lo -= 1;
+    }
+    let mut hi = median;
+    while dist(hi + 1) < limit {
+        hi += 1;
+    }
+    (lo, (lo..=hi).map(dist).collect())
+}
+
+// The total distance splits into an x part and a y part, so we only ever look
+// at the positions along each axis that could be in range, never the whole area.
+// With no points every cell is safe for a positive threshold, and there's no
+// region to give back.
+pub fn safe_region(points: &[(Id, Coord)], threshold: i64) -> Option<SafeRegion> {
+    if points.is_empty() {
+        return if threshold > 0 {
+            None
+        } else {
+            Some(SafeRegion {
+                threshold,
+                rows: Vec::new(),
+            })
+        };
+    }
+    let xs: Vec<i32> = points.iter().map(|(_, p)| p.0).collect();
+    let ys: Vec<i32> = points.iter().map(|(_, p)| p.1).collect();
+    // The sums are smallest at the median
+    let min_sum = |cs: &[i32]| {
+        let mut sorted = cs.to_vec();
+        sorted.sort();
+        let median = sorted.get(sorted.len() / 2).cloned().unwrap_or(0);
+        cs.iter()
+            .map(|c| (i64::from(*c) - i64::from(median)).abs())
+            .sum::<i64>()
+    };
+    let (x0, x_sums) = axis_sums(&xs, threshold - min_sum(&ys));
+    let (y0, y_sums) = axis_sums(&ys, threshold - min_sum(&xs));
+
+    let x_min = x_sums
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, s)| **s)
+        .map_or(0, |(i, _)| i);
+    let (falling, rising) = x_sums.split_at(x_min);
+
+    let mut rows = Vec::new();
+    for (i, y_sum) in y_sums.iter().enumerate() {
+        let limit = threshold - y_sum;
+        let lo = falling.partition_point(|s| *s >= limit);
+        let hi = x_min + rising.partition_point(|s| *s < limit);
+        if lo < hi {
+            let y = (y0 + i as i64) as i32;
+            rows.push((y, (x0 + lo as i64) as i32, (x0 + hi as i64 - 1) as i32));
+        }
+    }
+    Some(SafeRegion { threshold, rows })
+}
+
+#[aoc(day6, part2, separable)]
+pub fn part2_separable(points: &[(Id, Coord)]) -> usize {
+    safe_region(points, 10_000)
+        .expect("with no points every cell is safe")
+        .size()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts owners over a window big enough to hold every finite region
+    fn brute_force_areas(
+        points: &[(Id, Coord)],
+        metric: Metric,
+        r: i32,
+    ) -> Vec<(Id, Option<usize>)> {
+        let infinite = infinite_regions(points, metric);
+        let owners: Counter<Id> = (-r..=r)
+            .flat_map(|x| (-r..=r).map(move |y| (x, y)))
+            .filter_map(|c| owner(points, metric, c))
+            .collect();
+        points
+            .iter()
+            .map(|(id, _)| {
+                if infinite.contains(id) {
+                    (*id, None)
+                } else {
+                    (*id, Some(owners.get(id).cloned().unwrap_or(0)))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn example() {
+        let points = part1_g("1, 1\n1, 6\n8, 3\n3, 4\n5, 5\n8, 9");
+        assert_eq!(largest_finite_area(&points, Metric::Manhattan), 17);
+    }
+
+    #[test]
+    fn thin_euclidean_wedge() {
+        // Region 1's cells skip a whole ring around the box
+        let points = vec![
+            (0, (-2, 3)),
+            (1, (-1, -1)),
+            (2, (1, 6)),
+            (3, (-4, 5)),
+            (4, (1, -6)),
+            (5, (2, -2)),
+        ];
+        let areas = region_areas(&points, Metric::Euclidean);
+        assert_eq!(areas[1], (1, Some(223)));
+        assert_eq!(areas, brute_force_areas(&points, Metric::Euclidean, 200));
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut rand = move |n: i32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as i32
+        };
+        for _ in 0..15 {
+            let points: Vec<(Id, Coord)> = (0..6)
+                .map(|id| (id, (rand(13) - 6, rand(13) - 6)))
+                .collect();
+            for &metric in &[Metric::Manhattan, Metric::Euclidean, Metric::Chebyshev] {
+                assert_eq!(
+                    region_areas(&points, metric),
+                    brute_force_areas(&points, metric, 200),
+                    "{:?} {:?}",
+                    metric,
+                    points
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn safe_region_example() {
+        let points = part1_g("1, 1\n1, 6\n8, 3\n3, 4\n5, 5\n8, 9");
+        assert_eq!(safe_region(&points, 32).map(|r| r.size()), Some(16));
+    }
+
+    #[test]
+    fn safe_region_without_points() {
+        assert_eq!(safe_region(&[], 10_000), None);
+        assert_eq!(safe_region(&[], 0).map(|r| r.size()), Some(0));
+    }
+
+    #[test]
+    fn safe_region_matches_brute_force() {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut rand = move |n: i32| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as i32
+        };
+        for _ in 0..40 {
+            let points: Vec<(Id, Coord)> = (0..1 + rand(5) as usize)
+                .map(|id| (id, (rand(21) - 10, rand(21) - 10)))
+                .collect();
+            let threshold = i64::from(rand(100));
+            let region = safe_region(&points, threshold).unwrap();
+
+            // Every safe cell is closer than the threshold to each point
+            let r = 10 + threshold as i32;
+            let mut count = 0;
+            for x in -r..=r {
+                for y in -r..=r {
+                    let total: i64 = points
+                        .iter()
+                        .map(|(_, p)| Metric::Manhattan.distance((x, y), *p))
+                        .sum();
+                    assert_eq!(region.contains((x, y)), total < threshold);
+                    count += (total < threshold) as usize;
+                }
+            }
+            assert_eq!(region.size(), count, "{:?} {}", points, threshold);
+        }
+    }
+}
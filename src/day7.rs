@@ -1,192 +1,493 @@
-use std::collections::BinaryHeap;
-
-#[derive(Debug, PartialEq, Default)]
-struct Node {
-    dep_count: u32,
-    dependents: Vec<char>,
-}
+use crate::toposort::{Cycle, Graph, TieBreak};
+use regex::*;
+use std::collections::BTreeSet;
+use std::io::{self, Write};
 
 #[aoc(day7, part1)]
 pub fn part1(inp: &str) -> String {
-    let dep_pairs = parse_pairs(inp);
-    let graph = build_graph(dep_pairs);
-    println!("{:?}", graph);
-    let res = compute_path(graph);
-
-    res.into_iter().collect()
+    let graph = parse_graph(inp);
+    match graph.sort(&TieBreak::Lexicographic) {
+        Ok(order) => order.concat(),
+        Err(cycle) => panic!("{}", cycle),
+    }
 }
 
 #[aoc(day7, part2)]
 pub fn part2(inp: &str) -> usize {
-    let dep_pairs = parse_pairs(inp);
-    let graph = build_graph(dep_pairs);
-    compute_timing(graph)
+    let graph = parse_graph(inp);
+    schedule(
+        &graph,
+        5,
+        &TieBreak::Lexicographic,
+        base_duration(60),
+        |_, _| true,
+    )
+    .unwrap()
+    .total_time
 }
 
-// Vec of pairs where the first element is a dependency for the second
-fn parse_pairs(inp: &str) -> Vec<(char, char)> {
-    // Step Q must be finished before step I can begin.
-    inp.lines()
-        .map(|l| {
-            (
-                l.bytes().nth(5).unwrap() as char,
-                l.bytes().nth(36).unwrap() as char,
-            )
-        })
-        .collect()
+// Step Q must be finished before step I can begin.
+// Task names can be any run of non-space characters.
+pub fn parse_graph(inp: &str) -> Graph {
+    lazy_static! {
+        static ref STEP_RE: Regex =
+            Regex::new(r"^Step (\S+) must be finished before step (\S+) can begin\.$").unwrap();
+    }
+    let mut graph = Graph::new();
+    for l in inp.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let caps = STEP_RE
+            .captures(l)
+            .unwrap_or_else(|| panic!("bad step: {}", l));
+        graph.add_dependency(&caps[1], &caps[2]);
+    }
+    graph
 }
 
-fn build_graph(pairs: Vec<(char, char)>) -> [Node; 26] {
-    let mut res: [Node; 26] = Default::default();
+// The puzzle's durations: A takes base + 1 seconds, B base + 2 and so on.
+// Longer names go by their first letter, and names that don't start with a
+// capital letter take base + 1 like A.
+pub fn base_duration(base: usize) -> impl Fn(&str) -> usize {
+    move |task| match task.bytes().next() {
+        Some(c @ b'A'..=b'Z') => base + usize::from(c - b'A') + 1,
+        _ => base + 1,
+    }
+}
 
-    for (src, dst) in pairs {
-        let src_node = res.get_mut((src as u8 - b'A') as usize).unwrap();
-        src_node.dependents.push(dst);
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ScheduledTask {
+    pub task: String,
+    pub worker: usize,
+    pub start: usize,
+    pub end: usize,
+}
 
-        let dst_node = res.get_mut((dst as u8 - b'A') as usize).unwrap();
-        dst_node.dep_count += 1;
-    }
-    res
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Schedule {
+    pub total_time: usize,
+    // Ordered by start time, then worker
+    pub tasks: Vec<ScheduledTask>,
 }
 
-// The binary heap is a max heap and we need a min heap
-// The correct way to do this is to make a new type and
-// define a bunch of traits, but I'm in a bit of a hurry...
-fn flip_index(letter: u8) -> u8 {
-    25 - letter
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ScheduleError {
+    Cycle(Cycle),
+    // Tasks that could never be started, either because no worker is able to
+    // do them or because they're waiting on one of those
+    Stuck(Vec<String>),
 }
 
-fn compute_path(mut inp: [Node; 26]) -> Vec<char> {
-    let mut res = Vec::new();
-    let mut queue = BinaryHeap::new();
+// Runs the tasks with the given number of workers. Whenever workers are free
+// the first available task by the tie break goes to the lowest numbered free
+// worker that can_do(worker, task), and every task finishing at the same
+// moment is completed before anything new is handed out.
+pub fn schedule(
+    graph: &Graph,
+    workers: usize,
+    tie_break: &TieBreak,
+    duration: impl Fn(&str) -> usize,
+    can_do: impl Fn(usize, &str) -> bool,
+) -> Result<Schedule, ScheduleError> {
+    graph
+        .sort_indices(tie_break)
+        .map_err(ScheduleError::Cycle)?;
+    let rank = graph.ranks(tie_break);
 
-    for (i, e) in inp.iter().enumerate() {
-        if (*e).dep_count == 0 {
-            queue.push(flip_index(i as u8))
+    let mut dep_count: Vec<usize> = (0..graph.len())
+        .map(|t| graph.dependencies(t).len())
+        .collect();
+    // Ranks of the available tasks
+    let mut available: BTreeSet<(usize, usize)> = (0..graph.len())
+        .filter(|&t| dep_count[t] == 0)
+        .map(|t| (rank[t], t))
+        .collect();
+    let mut idle = vec![true; workers];
+    let mut running: Vec<(usize, ScheduledTask)> = Vec::new();
+    let mut done: Vec<(usize, ScheduledTask)> = Vec::new();
+    let mut time = 0;
+
+    loop {
+        for (r, task) in available.clone() {
+            let name = graph.name(task);
+            if let Some(worker) = (0..workers).find(|&w| idle[w] && can_do(w, name)) {
+                available.remove(&(r, task));
+                idle[worker] = false;
+                running.push((
+                    task,
+                    ScheduledTask {
+                        task: name.to_owned(),
+                        worker,
+                        start: time,
+                        end: time + duration(name),
+                    },
+                ));
+            }
         }
-    }
 
-    while let Some(inv_id) = queue.pop() {
-        let id = flip_index(inv_id);
-        res.push((id + b'A') as char);
-        let deps = {
-            let src = inp.get(id as usize).unwrap();
-            assert!(src.dep_count == 0);
-            src.dependents.clone()
+        time = match running.iter().map(|(_, t)| t.end).min() {
+            Some(t) => t,
+            None => break,
         };
-        for dep in deps {
-            let index = (dep as u8 - b'A') as usize;
-            let mut n = inp.get_mut(index).unwrap();
-            n.dep_count -= 1;
-            if n.dep_count == 0 {
-                queue.push(flip_index(index as u8))
+        let (mut finished, still_running): (Vec<_>, Vec<_>) =
+            running.into_iter().partition(|(_, t)| t.end == time);
+        running = still_running;
+        finished.sort_by_key(|(task, _)| rank[*task]);
+        for (task, t) in finished {
+            idle[t.worker] = true;
+            for &dep in graph.dependents(task) {
+                dep_count[dep] -= 1;
+                if dep_count[dep] == 0 {
+                    available.insert((rank[dep], dep));
+                }
             }
+            done.push((task, t));
         }
     }
 
-    res
+    if done.len() < graph.len() {
+        let mut finished = vec![false; graph.len()];
+        for (task, _) in &done {
+            finished[*task] = true;
+        }
+        let mut stuck: Vec<String> = (0..graph.len())
+            .filter(|&t| !finished[t])
+            .map(|t| graph.name(t).to_owned())
+            .collect();
+        stuck.sort();
+        return Err(ScheduleError::Stuck(stuck));
+    }
+    let mut tasks: Vec<ScheduledTask> = done.into_iter().map(|(_, t)| t).collect();
+    tasks.sort_by_key(|t| (t.start, t.worker));
+    Ok(Schedule {
+        total_time: time,
+        tasks,
+    })
 }
 
-// "Letters" are 0 indexed
-fn job_length(letter: u8) -> usize {
-    letter as usize + 60 + 1
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TaskTiming {
+    pub task: String,
+    pub duration: usize,
+    pub earliest_start: usize,
+    pub latest_start: usize,
+    // How long the task can be put off without delaying the whole job
+    pub slack: usize,
 }
 
-#[derive(Debug, Ord, PartialEq, Eq, PartialOrd)]
-struct Worker {
-    next_free: usize,
-    job: u8,
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CriticalPath {
+    // Time to finish everything with unlimited workers
+    pub length: usize,
+    // The longest dependency chain, first task first
+    pub chain: Vec<String>,
+    // Every task, in dependency order
+    pub tasks: Vec<TaskTiming>,
 }
 
-fn compute_timing(mut inp: [Node; 26]) -> usize {
-    let mut time = 0;
-    let mut free_workers = 5;
-    let mut workers = Vec::new();
-    let mut queue = BinaryHeap::new();
+impl CriticalPath {
+    pub fn is_critical(&self, task: &str) -> bool {
+        self.tasks.iter().any(|t| t.task == task && t.slack == 0)
+    }
+}
 
-    for (i, e) in inp.iter().enumerate() {
-        if (*e).dep_count == 0 {
-            queue.push(flip_index(i as u8))
-        }
+// Critical path method: a forward pass for the earliest each task can start,
+// then a backward pass for the latest it can start without pushing back the
+// end. Ignores the number of workers, so the schedule's total_time is at
+// least `length` and any difference is time spent waiting for a worker.
+pub fn critical_path(
+    graph: &Graph,
+    duration: impl Fn(&str) -> usize,
+) -> Result<CriticalPath, Cycle> {
+    let order = graph.sort_indices(&TieBreak::Lexicographic)?;
+    let durations: Vec<usize> = (0..graph.len()).map(|t| duration(graph.name(t))).collect();
+
+    let mut earliest = vec![0; graph.len()];
+    for &t in &order {
+        earliest[t] = graph
+            .dependencies(t)
+            .iter()
+            .map(|&d| earliest[d] + durations[d])
+            .max()
+            .unwrap_or(0);
     }
+    let length = order
+        .iter()
+        .map(|&t| earliest[t] + durations[t])
+        .max()
+        .unwrap_or(0);
 
-    // Assign initial jobs to workers
-    while free_workers > 0 {
-        if let Some(inv_id) = queue.pop() {
-            let job = flip_index(inv_id);
-            let next_free = job_length(job) + time;
-            println!(
-                "Time: {} worker starting {} until {}",
-                time,
-                show_job_id(job),
-                next_free
-            );
-            workers.push(Worker { next_free, job });
-            free_workers -= 1;
-        } else {
-            break;
-        }
+    let mut latest = vec![0; graph.len()];
+    for &t in order.iter().rev() {
+        let finish = graph
+            .dependents(t)
+            .iter()
+            .map(|&d| latest[d])
+            .min()
+            .unwrap_or(length);
+        latest[t] = finish - durations[t];
     }
 
-    println!("Begin time");
-    while free_workers < 5 {
-        let worker = {
-            workers.sort();
-            workers.remove(0)
-        };
-        let id = worker.job;
-        time = worker.next_free;
-        free_workers += 1;
-        println!(
-            "Job {} finished at time: {}, available workers: {}",
-            show_job_id(id),
-            time,
-            free_workers
-        );
+    // Walk back from a task that ends last through dependencies that end
+    // exactly when it could start
+    let mut chain = Vec::new();
+    let mut current = order
+        .iter()
+        .cloned()
+        .find(|&t| earliest[t] + durations[t] == length);
+    while let Some(t) = current {
+        chain.push(graph.name(t).to_owned());
+        current = order.iter().cloned().find(|&d| {
+            graph.dependencies(t).contains(&d) && earliest[d] + durations[d] == earliest[t]
+        });
+    }
+    chain.reverse();
 
-        // Should probably check to make sure the next worker isn't
-        // also finished now...
-        // update available jobs
-        let deps = {
-            let src = inp.get(id as usize).unwrap();
-            assert!(src.dep_count == 0);
-            src.dependents.clone()
-        };
-        for dep in deps {
-            let index = letter_to_index(dep);
-            let mut n = inp.get_mut(index).unwrap();
-            n.dep_count -= 1;
-            if n.dep_count == 0 {
-                println!("{} is now available for work", show_job_id(index as u8));
-                queue.push(flip_index(index as u8))
-            }
+    let tasks = order
+        .iter()
+        .map(|&t| TaskTiming {
+            task: graph.name(t).to_owned(),
+            duration: durations[t],
+            earliest_start: earliest[t],
+            latest_start: latest[t],
+            slack: latest[t] - earliest[t],
+        })
+        .collect();
+    Ok(CriticalPath {
+        length,
+        chain,
+        tasks,
+    })
+}
+
+impl Schedule {
+    pub fn workers(&self) -> usize {
+        self.tasks.iter().map(|t| t.worker + 1).max().unwrap_or(0)
+    }
+
+    // One row per worker with `scale` seconds to a column. Each task is its
+    // name followed by `-`, or `=` for tasks on the critical path, and idle
+    // time is `.`.
+    pub fn write_gantt_text(
+        &self,
+        out: &mut impl Write,
+        scale: usize,
+        critical: &CriticalPath,
+    ) -> io::Result<()> {
+        let scale = std::cmp::max(scale, 1);
+        let columns = self.total_time.div_ceil(scale);
+
+        let mut axis = String::new();
+        while axis.len() < columns {
+            axis.push_str(&format!("{:<10}", axis.len() * scale));
         }
-        // Assign any workers to spare jobs
-        while free_workers > 0 {
-            if let Some(inv_id) = queue.pop() {
-                let job = flip_index(inv_id);
-                let next_free = job_length(job) + time;
-                println!(
-                    "Time: {} worker starting {} until {}",
-                    time,
-                    show_job_id(job),
-                    next_free
-                );
-                workers.push(Worker { next_free, job });
-                free_workers -= 1;
-            } else {
-                break;
+        writeln!(out, "{:>4}  {}", "", axis.trim_end())?;
+
+        for worker in 0..self.workers() {
+            let mut row = vec!['.'; columns];
+            for t in self.tasks.iter().filter(|t| t.worker == worker) {
+                let fill = if critical.is_critical(&t.task) {
+                    '='
+                } else {
+                    '-'
+                };
+                let (from, to) = (t.start / scale, t.end.div_ceil(scale));
+                let mut name = t.task.chars();
+                for c in &mut row[from..to] {
+                    *c = name.next().unwrap_or(fill);
+                }
             }
+            writeln!(
+                out,
+                "{:>4}  {}",
+                worker,
+                row.into_iter().collect::<String>()
+            )?;
+        }
+
+        writeln!(
+            out,
+            "Total time {}, critical path {} ({})",
+            self.total_time,
+            critical.length,
+            critical.chain.join(" -> ")
+        )?;
+        if self.total_time > critical.length {
+            writeln!(
+                out,
+                "{} spent waiting for workers",
+                self.total_time - critical.length
+            )?;
         }
+        Ok(())
     }
 
-    time
+    // Same layout as the text chart with a pixel per second, critical tasks in
+    // red and every other task in grey
+    pub fn write_gantt_svg(&self, out: &mut impl Write, critical: &CriticalPath) -> io::Result<()> {
+        const ROW: usize = 20;
+        const MARGIN: usize = 30;
+        let width = self.total_time + MARGIN * 2;
+        let height = self.workers() * ROW + MARGIN * 2;
+
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="monospace" font-size="10">"#,
+            w = width,
+            h = height
+        )?;
+        writeln!(
+            out,
+            r##"<rect width="{}" height="{}" fill="#ffffff"/>"##,
+            width, height
+        )?;
+        for worker in 0..self.workers() {
+            writeln!(
+                out,
+                r#"<text x="2" y="{}">{}</text>"#,
+                MARGIN + worker * ROW + ROW / 2 + 4,
+                worker
+            )?;
+        }
+        for t in &self.tasks {
+            let fill = if critical.is_critical(&t.task) {
+                "#d62728"
+            } else {
+                "#999999"
+            };
+            let (x, y) = (MARGIN + t.start, MARGIN + t.worker * ROW);
+            writeln!(
+                out,
+                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="#ffffff"><title>{} {}-{}</title></rect>"##,
+                x,
+                y + 2,
+                t.end - t.start,
+                ROW - 4,
+                fill,
+                xml_escape(&t.task),
+                t.start,
+                t.end
+            )?;
+            writeln!(
+                out,
+                r#"<text x="{}" y="{}">{}</text>"#,
+                x + 2,
+                y + ROW / 2 + 4,
+                xml_escape(&t.task)
+            )?;
+        }
+        writeln!(
+            out,
+            r#"<text x="{}" y="{}">total {}, critical path {}</text>"#,
+            MARGIN,
+            height - MARGIN / 2,
+            self.total_time,
+            critical.length
+        )?;
+        writeln!(out, "</svg>")
+    }
 }
 
-fn show_job_id(id: u8) -> char {
-    (id + (b'A')) as char
+// Task names can be any non-space characters, so they need escaping in SVG
+fn xml_escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            _ => res.push(c),
+        }
+    }
+    res
 }
 
-fn letter_to_index(letter: char) -> usize {
-    (letter as u8 - b'A') as usize
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.
+Step A must be finished before step B can begin.
+Step A must be finished before step D can begin.
+Step B must be finished before step E can begin.
+Step D must be finished before step E can begin.
+Step F must be finished before step E can begin.";
+
+    #[test]
+    fn example_order() {
+        assert_eq!(part1(EXAMPLE), "CABDFE");
+    }
+
+    #[test]
+    fn example_schedule() {
+        let graph = parse_graph(EXAMPLE);
+        let plan = schedule(
+            &graph,
+            2,
+            &TieBreak::Lexicographic,
+            base_duration(0),
+            |_, _| true,
+        )
+        .unwrap();
+        assert_eq!(plan.total_time, 15);
+
+        let task = |task: &str, worker, start, end| ScheduledTask {
+            task: task.to_owned(),
+            worker,
+            start,
+            end,
+        };
+        assert_eq!(
+            plan.tasks,
+            vec![
+                task("C", 0, 0, 3),
+                task("A", 0, 3, 4),
+                task("F", 1, 3, 9),
+                task("B", 0, 4, 6),
+                task("D", 0, 6, 10),
+                task("E", 0, 10, 15),
+            ]
+        );
+
+        // The tasks are listed by when they start, while the puzzle gives
+        // the order they finish in
+        let started: String = plan.tasks.iter().map(|t| t.task.as_str()).collect();
+        assert_eq!(started, "CAFBDE");
+        let mut finished = plan.tasks.clone();
+        finished.sort_by_key(|t| t.end);
+        let finished: String = finished.iter().map(|t| t.task.as_str()).collect();
+        assert_eq!(finished, "CABFDE");
+    }
+
+    #[test]
+    fn durations_for_any_name() {
+        let duration = base_duration(60);
+        assert_eq!(duration("A"), 61);
+        assert_eq!(duration("Z"), 86);
+        assert_eq!(duration("Zeta"), 86);
+        assert_eq!(duration("1"), 61);
+        assert_eq!(duration("a"), 61);
+        assert_eq!(duration(""), 61);
+    }
+
+    #[test]
+    fn numeric_names() {
+        let inp = "Step 1 must be finished before step 2 can begin.\n\
+                   Step 2 must be finished before step 10 can begin.";
+        assert_eq!(part1(inp), "1210");
+        assert_eq!(part2(inp), 61 * 3);
+    }
+
+    #[test]
+    fn svg_escapes_names() {
+        let graph = parse_graph("Step a<b must be finished before step \"c&d\" can begin.");
+        let plan = schedule(&graph, 2, &TieBreak::Lexicographic, |_| 3, |_, _| true).unwrap();
+        let critical = critical_path(&graph, |_| 3).unwrap();
+        let mut out = Vec::new();
+        plan.write_gantt_svg(&mut out, &critical).unwrap();
+        let svg = String::from_utf8(out).unwrap();
+        assert!(svg.contains("<title>a&lt;b 0-3</title>"));
+        assert!(svg.contains("&quot;c&amp;d&quot;"));
+        assert!(!svg.contains("a<b"));
+    }
 }
@@ -1,69 +1,587 @@
-struct ParsedNode<'a> {
-    children: Vec<ParsedNode<'a>>,
-    metadata: &'a [u8],
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+// Clone, PartialEq, Debug and Drop are written out by hand because the derived
+// ones recurse, and would overflow the stack on very deep trees
+#[derive(Default)]
+pub struct LicenseNode {
+    pub children: Vec<LicenseNode>,
+    pub metadata: Vec<usize>,
 }
 
-fn create_node(inp: &[u8]) -> (ParsedNode, &[u8]) {
-    let mut child_count = inp[0];
-    let metadata_length = inp[1] as usize;
-    let mut children = Vec::new();
-    let mut rest = &inp[2..];
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseError {
+    // Ran out of numbers at this position while reading a node that
+    // started at `node`
+    UnexpectedEnd { position: usize, node: usize },
+    // The root node finished here but there were more numbers
+    TrailingInput { position: usize },
+    // The token at this position isn't a number that fits in a usize
+    BadToken { position: usize, token: String },
+    // A total for the node that started at `node` doesn't fit in a usize
+    Overflow { node: usize },
+    Io(io::ErrorKind),
+}
 
-    while child_count > 0 {
-        let (n, next_rest) = create_node(&rest);
-        rest = next_rest;
-        children.push(n);
-        child_count -= 1;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedEnd { position, node } => write!(
+                f,
+                "input ends at number {} in the middle of the node at {}",
+                position, node
+            ),
+            ParseError::TrailingInput { position } => {
+                write!(f, "unexpected numbers after the tree at {}", position)
+            }
+            ParseError::BadToken { position, token } => {
+                write!(f, "number {} is not a valid number: {:?}", position, token)
+            }
+            ParseError::Overflow { node } => {
+                write!(f, "total for the node at {} is too big", node)
+            }
+            ParseError::Io(kind) => write!(f, "error reading the tree: {:?}", kind),
+        }
     }
-    let metadata = &rest[..metadata_length];
-    (ParsedNode { children, metadata }, &rest[metadata_length..])
 }
 
-#[aoc(day8, part1)]
-pub fn part1(inp: &str) -> usize {
-    let nums: Vec<u8> = inp
-        .split_whitespace()
-        .map(|x| x.parse())
-        .filter_map(|x| x.ok())
-        .collect();
-    let (node, rest) = create_node(&nums[..]);
-    assert!(rest.is_empty());
-    part1_computation(&node)
+impl LicenseNode {
+    pub fn new(children: Vec<LicenseNode>, metadata: Vec<usize>) -> LicenseNode {
+        LicenseNode { children, metadata }
+    }
+
+    pub fn leaf(metadata: Vec<usize>) -> LicenseNode {
+        LicenseNode::new(Vec::new(), metadata)
+    }
+
+    pub fn parse(nums: &[usize]) -> Result<LicenseNode, ParseError> {
+        fold_numbers(nums.iter().map(|n| Ok(*n)), |children, metadata| {
+            Some(LicenseNode::new(children, metadata.to_vec()))
+        })
+    }
+
+    // The flat format the puzzle uses: child count, metadata count, the
+    // children, then the metadata
+    pub fn to_numbers(&self) -> Vec<usize> {
+        let mut res = Vec::new();
+        self.write_numbers(&mut res);
+        res
+    }
+
+    fn write_numbers(&self, out: &mut Vec<usize>) {
+        // (node, index of the next child to write)
+        let mut stack = vec![(self, 0)];
+        out.push(self.children.len());
+        out.push(self.metadata.len());
+        while let Some((node, next)) = stack.last_mut() {
+            let node: &LicenseNode = node;
+            match node.children.get(*next) {
+                Some(child) => {
+                    *next += 1;
+                    out.push(child.children.len());
+                    out.push(child.metadata.len());
+                    stack.push((child, 0));
+                }
+                None => {
+                    out.extend_from_slice(&node.metadata);
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    // Post-order fold: f(node, results for its children, depth) runs once for
+    // every node, children first. The root is at depth 0. Uses an explicit
+    // stack, so it works on trees of any depth.
+    pub fn fold<T>(&self, mut f: impl FnMut(&LicenseNode, Vec<T>, usize) -> T) -> T {
+        let mut stack: Vec<(&LicenseNode, Vec<T>)> = vec![(self, Vec::new())];
+        loop {
+            let depth = stack.len() - 1;
+            let (node, done) = stack.last().unwrap();
+            if let Some(child) = node.children.get(done.len()) {
+                stack.push((child, Vec::with_capacity(child.children.len())));
+                continue;
+            }
+            let (node, done) = stack.pop().unwrap();
+            let res = f(node, done, depth);
+            match stack.last_mut() {
+                Some((_, parent)) => parent.push(res),
+                None => return res,
+            }
+        }
+    }
+
+    // Pre-order walk calling f(node, depth) on each node before its children
+    pub fn walk(&self, mut f: impl FnMut(&LicenseNode, usize)) {
+        let mut stack = vec![(self, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            f(node, depth);
+            stack.extend(node.children.iter().rev().map(|c| (c, depth + 1)));
+        }
+    }
+
+    // The part 2 value. This and the totals below are None if they don't fit
+    // in a usize.
+    pub fn value(&self) -> Option<usize> {
+        self.fold(|node, children: Vec<Option<usize>>, _| {
+            let children: Option<Vec<usize>> = children.into_iter().collect();
+            node_value(&node.metadata, &children?)
+        })
+    }
+
+    pub fn metadata_sum(&self) -> Option<usize> {
+        self.fold(|node, children: Vec<Option<usize>>, _| {
+            checked_sum(
+                children
+                    .into_iter()
+                    .chain(node.metadata.iter().map(|&m| Some(m))),
+            )
+        })
+    }
+
+    // Metadata sum with every entry multiplied by how deep its node is,
+    // counting the root as 1
+    pub fn depth_weighted_sum(&self) -> Option<usize> {
+        self.fold(|node, children: Vec<Option<usize>>, depth| {
+            let own = checked_sum(node.metadata.iter().map(|&m| Some(m)))?.checked_mul(depth + 1);
+            checked_sum(children.into_iter().chain(Some(own)))
+        })
+    }
+
+    // The largest part 2 value of any node in the tree
+    pub fn max_node_value(&self) -> Option<usize> {
+        // (value, best in the subtree)
+        let (_, best) = self.fold(|node, children: Vec<Option<(usize, usize)>>, _| {
+            let children = children.into_iter().collect::<Option<Vec<_>>>()?;
+            let values: Vec<usize> = children.iter().map(|c| c.0).collect();
+            let value = node_value(&node.metadata, &values)?;
+            let best = children.iter().map(|c| c.1).fold(value, std::cmp::max);
+            Some((value, best))
+        })?;
+        Some(best)
+    }
+
+    // Metadata entries of nodes with children that don't refer to any child,
+    // so count for nothing in part 2
+    pub fn dangling_references(&self) -> usize {
+        let mut res = 0;
+        self.walk(|node, _| {
+            if !node.children.is_empty() {
+                res += node
+                    .metadata
+                    .iter()
+                    .filter(|&&i| i == 0 || i > node.children.len())
+                    .count();
+            }
+        });
+        res
+    }
+
+    // One node per line, indented two spaces per level
+    pub fn write_pretty(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut res = Ok(());
+        self.walk(|node, depth| {
+            if res.is_ok() {
+                res = writeln!(
+                    out,
+                    "{:indent$}{} children, metadata {:?}",
+                    "",
+                    node.children.len(),
+                    node.metadata,
+                    indent = depth * 2
+                );
+            }
+        });
+        res
+    }
 }
 
-fn part1_computation(n: &ParsedNode) -> usize {
-    n.children.iter().map(part1_computation).sum::<usize>()
-        + n.metadata.iter().map(|x| *x as usize).sum::<usize>()
+impl Clone for LicenseNode {
+    fn clone(&self) -> LicenseNode {
+        self.fold(|node, children, _| LicenseNode::new(children, node.metadata.clone()))
+    }
 }
 
-// Because the nodes are just referring to slices of the input I can't
-// use a cargo aoc generator because it doesn't pass the lifetimes on properly
-#[aoc(day8, part2)]
-pub fn part2(inp: &str) -> usize {
-    let nums: Vec<u8> = inp
-        .split_whitespace()
-        .map(|x| x.parse())
-        .filter_map(|x| x.ok())
-        .collect();
-    let (node, rest) = create_node(&nums[..]);
-    assert!(rest.is_empty());
+impl PartialEq for LicenseNode {
+    fn eq(&self, other: &LicenseNode) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            if a.metadata != b.metadata || a.children.len() != b.children.len() {
+                return false;
+            }
+            stack.extend(a.children.iter().zip(b.children.iter()));
+        }
+        true
+    }
+}
 
-    get_node_value(&node)
+impl Eq for LicenseNode {}
+
+impl Drop for LicenseNode {
+    // Moves the descendants into a work list so each node is dropped with no
+    // children left
+    fn drop(&mut self) {
+        let mut work = std::mem::take(&mut self.children);
+        while let Some(mut node) = work.pop() {
+            work.append(&mut node.children);
+        }
+    }
 }
 
-fn get_node_value(node: &ParsedNode) -> usize {
-    if node.children.is_empty() {
-        node.metadata.iter().map(|x| *x as usize).sum()
-    } else {
-        let mut res = 0;
-        for ind in node.metadata.iter() {
-            if *ind == 0 {
-                continue;
+// Prints what the derived Debug would, though {:#?} is all on one line too
+impl fmt::Debug for LicenseNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // (node, index of the next child to write)
+        let mut stack = vec![(self, 0)];
+        write!(f, "LicenseNode {{ children: [")?;
+        while let Some((node, next)) = stack.last_mut() {
+            let node: &LicenseNode = node;
+            match node.children.get(*next) {
+                Some(child) => {
+                    if *next > 0 {
+                        write!(f, ", ")?;
+                    }
+                    *next += 1;
+                    write!(f, "LicenseNode {{ children: [")?;
+                    stack.push((child, 0));
+                }
+                None => {
+                    write!(f, "], metadata: {:?} }}", node.metadata)?;
+                    stack.pop();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LicenseNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nums: Vec<String> = self.to_numbers().iter().map(|n| n.to_string()).collect();
+        write!(f, "{}", nums.join(" "))
+    }
+}
+
+// A node that's been started but not finished
+struct Frame<T> {
+    start: usize,
+    children_left: usize,
+    metadata_length: usize,
+    children: Vec<T>,
+}
+
+// Reads one tree from a stream of numbers, calling finish(children, metadata)
+// on every node once its children are done, which gives None if the node's
+// result overflows. The pending nodes are kept on an explicit stack so there's
+// no limit on the depth, and only the results for the nodes on that stack are
+// held in memory.
+pub fn fold_numbers<T>(
+    nums: impl IntoIterator<Item = Result<usize, ParseError>>,
+    mut finish: impl FnMut(Vec<T>, &[usize]) -> Option<T>,
+) -> Result<T, ParseError> {
+    let mut nums = nums.into_iter();
+    let mut position = 0;
+    let mut next = |node: usize| -> Result<usize, ParseError> {
+        let n = nums
+            .next()
+            .unwrap_or(Err(ParseError::UnexpectedEnd { position, node }))?;
+        position += 1;
+        Ok(n)
+    };
+
+    let mut stack: Vec<Frame<T>> = Vec::new();
+    let mut metadata = Vec::new();
+    let mut start = 0;
+    loop {
+        // Read a node header
+        let children_left = next(start)?;
+        let metadata_length = next(start)?;
+        stack.push(Frame {
+            start,
+            children_left,
+            metadata_length,
+            // The count comes straight from the input, so don't trust it
+            // with an allocation
+            children: Vec::new(),
+        });
+        start += 2;
+
+        // Finish off every node that has all its children
+        while stack.last().is_some_and(|f| f.children_left == 0) {
+            let frame = stack.pop().unwrap();
+            metadata.clear();
+            for _ in 0..frame.metadata_length {
+                metadata.push(next(frame.start)?);
             }
-            if let Some(child) = node.children.get(*ind as usize - 1) {
-                res += get_node_value(child);
+            start += frame.metadata_length;
+            let res = finish(frame.children, &metadata)
+                .ok_or(ParseError::Overflow { node: frame.start })?;
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(res),
+                None => {
+                    return match next(start) {
+                        Ok(_) => Err(ParseError::TrailingInput { position: start }),
+                        Err(ParseError::UnexpectedEnd { .. }) => Ok(res),
+                        Err(e) => Err(e),
+                    };
+                }
             }
         }
-        res
+        stack.last_mut().unwrap().children_left -= 1;
+    }
+}
+
+// Whitespace separated numbers from a reader
+pub struct Numbers<R> {
+    bytes: io::Bytes<R>,
+    position: usize,
+}
+
+impl<R: BufRead> Numbers<R> {
+    pub fn new(reader: R) -> Numbers<R> {
+        Numbers {
+            bytes: reader.bytes(),
+            position: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Numbers<R> {
+    type Item = Result<usize, ParseError>;
+
+    fn next(&mut self) -> Option<Result<usize, ParseError>> {
+        let mut token = Vec::new();
+        for b in &mut self.bytes {
+            match b {
+                Ok(b) if b.is_ascii_whitespace() => {
+                    if !token.is_empty() {
+                        break;
+                    }
+                }
+                Ok(b) => token.push(b),
+                Err(e) => return Some(Err(ParseError::Io(e.kind()))),
+            }
+        }
+        if token.is_empty() {
+            return None;
+        }
+        let position = self.position;
+        self.position += 1;
+        Some(parse_number(position, &String::from_utf8_lossy(&token)))
+    }
+}
+
+fn parse_number(position: usize, token: &str) -> Result<usize, ParseError> {
+    token.parse().map_err(|_| ParseError::BadToken {
+        position,
+        token: token.to_owned(),
+    })
+}
+
+// Whitespace separated numbers from a string
+pub fn numbers(inp: &str) -> impl Iterator<Item = Result<usize, ParseError>> + '_ {
+    inp.split_whitespace()
+        .enumerate()
+        .map(|(position, token)| parse_number(position, token))
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Totals {
+    // Part 1: the sum of all the metadata
+    pub metadata_sum: usize,
+    // Part 2
+    pub root_value: usize,
+}
+
+// Both answers in one pass without building the tree
+pub fn stream_totals(
+    nums: impl IntoIterator<Item = Result<usize, ParseError>>,
+) -> Result<Totals, ParseError> {
+    fold_numbers(nums, |children: Vec<Totals>, metadata| {
+        let values: Vec<usize> = children.iter().map(|c| c.root_value).collect();
+        Some(Totals {
+            metadata_sum: checked_sum(
+                children
+                    .iter()
+                    .map(|c| Some(c.metadata_sum))
+                    .chain(metadata.iter().map(|&m| Some(m))),
+            )?,
+            root_value: node_value(metadata, &values)?,
+        })
+    })
+}
+
+pub fn read_totals(reader: impl BufRead) -> Result<Totals, ParseError> {
+    stream_totals(Numbers::new(reader))
+}
+
+#[aoc_generator(day8)]
+pub fn parse_tree(inp: &str) -> Box<LicenseNode> {
+    let nums: Vec<usize> = numbers(inp)
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| panic!("{}", e));
+    Box::new(LicenseNode::parse(&nums).unwrap_or_else(|e| panic!("{}", e)))
+}
+
+#[aoc(day8, part1)]
+pub fn part1(tree: &LicenseNode) -> usize {
+    tree.metadata_sum().expect("metadata sum is too big")
+}
+
+#[aoc(day8, part2)]
+pub fn part2(tree: &LicenseNode) -> usize {
+    tree.value().expect("root value is too big")
+}
+
+// None as soon as the total overflows, or if any of the values already has
+fn checked_sum(values: impl IntoIterator<Item = Option<usize>>) -> Option<usize> {
+    values
+        .into_iter()
+        .try_fold(0usize, |total, v| total.checked_add(v?))
+}
+
+// A leaf is worth its metadata sum, any other node the sum of the children
+// its metadata entries point at (1 is the first child)
+fn node_value(metadata: &[usize], children: &[usize]) -> Option<usize> {
+    if children.is_empty() {
+        checked_sum(metadata.iter().map(|&m| Some(m)))
+    } else {
+        checked_sum(
+            metadata
+                .iter()
+                .filter(|&&i| i > 0)
+                .filter_map(|&i| children.get(i - 1))
+                .map(|&v| Some(v)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
+
+    fn round_trips(tree: &LicenseNode) {
+        assert_eq!(LicenseNode::parse(&tree.to_numbers()).unwrap(), *tree);
+    }
+
+    #[test]
+    fn example_round_trips() {
+        let nums: Vec<usize> = numbers(EXAMPLE).collect::<Result<_, _>>().unwrap();
+        let tree = LicenseNode::parse(&nums).unwrap();
+        assert_eq!(tree.to_numbers(), nums);
+        assert_eq!(tree.to_string(), EXAMPLE);
+        assert_eq!(part1(&tree), 138);
+        assert_eq!(part2(&tree), 66);
+    }
+
+    #[test]
+    fn debug_matches_derived() {
+        // What #[derive(Debug)] would print
+        let tree = LicenseNode::new(
+            vec![
+                LicenseNode::leaf(vec![10, 11, 12]),
+                LicenseNode::new(vec![LicenseNode::leaf(vec![99])], vec![2]),
+            ],
+            vec![1, 1, 2],
+        );
+        assert_eq!(
+            format!("{:?}", tree),
+            "LicenseNode { children: [\
+             LicenseNode { children: [], metadata: [10, 11, 12] }, \
+             LicenseNode { children: [LicenseNode { children: [], metadata: [99] }], \
+             metadata: [2] }], metadata: [1, 1, 2] }"
+        );
+    }
+
+    #[test]
+    fn leaf_round_trips() {
+        round_trips(&LicenseNode::leaf(vec![1, 2, 3]));
+        round_trips(&LicenseNode::leaf(Vec::new()));
+    }
+
+    #[test]
+    fn wide_round_trips() {
+        let children = (0..1000).map(|i| LicenseNode::leaf(vec![i])).collect();
+        round_trips(&LicenseNode::new(children, vec![1, 500, 1000]));
+    }
+
+    #[test]
+    fn large_metadata_round_trips() {
+        let children = (0..300).map(|i| LicenseNode::leaf(vec![i, 1000])).collect();
+        let tree = LicenseNode::new(children, vec![300, 256, usize::MAX]);
+        round_trips(&tree);
+        // Child 300 is worth 299 + 1000 and child 256 is worth 255 + 1000
+        assert_eq!(tree.value(), Some(2554));
+    }
+
+    #[test]
+    fn deep_round_trips() {
+        let mut tree = LicenseNode::leaf(vec![3]);
+        for _ in 0..200_000 {
+            tree = LicenseNode::new(vec![tree], vec![1]);
+        }
+        round_trips(&tree);
+        assert_eq!(tree.clone(), tree);
+        let debug = format!("{:?}", tree);
+        assert!(debug.starts_with("LicenseNode { children: [LicenseNode { children: ["));
+        assert!(debug.ends_with("metadata: [1] }], metadata: [1] }"));
+        assert_eq!(tree.value(), Some(3));
+        assert_eq!(tree.metadata_sum(), Some(200_003));
+    }
+
+    #[test]
+    fn huge_counts_are_errors() {
+        // Fails reading the first child's header
+        let err = ParseError::UnexpectedEnd {
+            position: 2,
+            node: 2,
+        };
+        let nums = [usize::MAX, 0];
+        assert_eq!(LicenseNode::parse(&nums), Err(err.clone()));
+        assert_eq!(stream_totals(numbers("18446744073709551615 0")), Err(err));
+        assert!(LicenseNode::parse(&[1 << 40, 1 << 40]).is_err());
+    }
+
+    #[test]
+    fn overflowing_totals() {
+        let inp = "0 2 18446744073709551615 1";
+        let tree = parse_tree(inp);
+        assert_eq!(tree.metadata_sum(), None);
+        assert_eq!(tree.value(), None);
+        assert_eq!(tree.depth_weighted_sum(), None);
+        assert_eq!(tree.max_node_value(), None);
+        let err = Err(ParseError::Overflow { node: 0 });
+        assert_eq!(stream_totals(numbers(inp)), err);
+        assert_eq!(read_totals(inp.as_bytes()), err);
+
+        // Only the depth weighting overflows
+        let tree = LicenseNode::new(vec![LicenseNode::leaf(vec![usize::MAX / 2 + 1])], vec![]);
+        assert_eq!(tree.metadata_sum(), Some(usize::MAX / 2 + 1));
+        assert_eq!(tree.depth_weighted_sum(), None);
+    }
+
+    #[test]
+    fn overflowing_values() {
+        // Every node counts its only child twice, so the value doubles each
+        // level up while the metadata sum stays small
+        let mut tree = LicenseNode::leaf(vec![1]);
+        for _ in 0..70 {
+            tree = LicenseNode::new(vec![tree], vec![1, 1]);
+        }
+        assert_eq!(tree.metadata_sum(), Some(141));
+        assert_eq!(tree.value(), None);
+        assert_eq!(tree.max_node_value(), None);
+        // The first to overflow is the one worth 2^64, six levels down
+        assert_eq!(
+            stream_totals(tree.to_numbers().into_iter().map(Ok)),
+            Err(ParseError::Overflow { node: 2 * 6 })
+        );
+        let mut node = &tree;
+        for _ in 0..7 {
+            node = &node.children[0];
+        }
+        assert_eq!(node.value(), Some(1 << 63));
     }
 }
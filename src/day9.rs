@@ -1,3 +1,5 @@
+use crate::circbuffer::CircBuffer;
+
 #[derive(Debug, Clone, Copy)]
 pub struct Input {
     player_count: usize,
@@ -66,109 +68,141 @@ fn solve(player_count: usize, last_marble: usize) -> usize {
 }
 
 fn solve_better(player_count: usize, last_marble: usize) -> usize {
-    let mut players = Vec::with_capacity(player_count);
-    for _ in 0..player_count {
-        (players.push(0));
-    }
+    play(player_count, last_marble, &Rules::default()).high_score()
+}
 
-    let mut marbles = CircBuffer::new();
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Rules {
+    // Marbles that are a multiple of this are scored instead of placed
+    pub special_every: usize,
+    // Where the scored marble's partner is taken from, relative to the
+    // current marble (negative is counter clockwise)
+    pub removal_offset: isize,
+    // Where other marbles go, relative to the current marble
+    pub insert_offset: isize,
+}
 
-    for marble in 1..=last_marble {
-        let player = marble % player_count;
-        if marble % 23 == 0 {
-            for _ in 0..7 {
-                marbles.counter_clockwise();
-            }
-            let removed = marbles.pop();
-            players[player] += marble + removed as usize;
-        } else {
-            marbles.clockwise();
-            marbles.clockwise();
-            marbles.push(marble as u32);
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            special_every: 23,
+            removal_offset: -7,
+            insert_offset: 2,
         }
-        //marbles.show();
     }
-
-    *players.iter().max().unwrap()
 }
 
-// A Zipper inspired circular buffer with a focus
-// the cw vec is 'backwards' to enable efficiently
-// pushing and popping of the elements
-//
-// Doing this avoids an enormous amount of shuffling vec
-// elements back and forth as items are inserted and removed
-struct CircBuffer {
-    ccw: Vec<u32>,
-    // This should be an Option<T>, but that isn't really necessary for this...
-    focus: u32,
-    cw: Vec<u32>,
+// Players are numbered from 1 like the puzzle, so player p's entries are at
+// index p - 1
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Game {
+    pub scores: Vec<usize>,
+    // (marble, score afterwards) for every marble that scored for the player
+    pub history: Vec<Vec<(usize, usize)>>,
+    // The player with the highest score, the first to get there on a tie
+    pub winner: Option<usize>,
+    // The marble after which a different player was ahead
+    pub last_lead_change: Option<usize>,
 }
 
-impl CircBuffer {
-    fn new() -> Self {
-        CircBuffer {
-            ccw: Vec::new(),
-            focus: 0,
-            cw: Vec::new(),
-        }
+impl Game {
+    pub fn high_score(&self) -> usize {
+        self.scores.iter().cloned().max().unwrap_or(0)
     }
+}
+
+pub fn play(player_count: usize, last_marble: usize, rules: &Rules) -> Game {
+    let mut scores = vec![0; player_count];
+    let mut history = vec![Vec::new(); player_count];
+    let mut leader: Option<usize> = None;
+    let mut last_lead_change = None;
 
-    // Remove the focus and slide the next item cw around in its place
-    // It is an error to remove the last element
-    fn pop(self: &mut Self) -> u32 {
-        let res = self.focus;
-        match self.cw.pop() {
-            Some(new_focus) => self.focus = new_focus,
-            None => {
-                std::mem::swap(&mut self.cw, &mut self.ccw);
-                self.cw.reverse();
-                self.focus = self.cw.pop().expect("Tried to pop from a 1 element buffer");
+    let mut marbles = CircBuffer::with_capacity(last_marble + 1);
+    marbles.insert(0);
+
+    for marble in 1..=last_marble {
+        let player = (marble - 1) % player_count;
+        if marble.is_multiple_of(rules.special_every) {
+            marbles.rotate(rules.removal_offset);
+            // Only a rule with no marbles placed in between can empty the circle
+            let removed = marbles.remove().unwrap_or(0);
+            scores[player] += marble + removed;
+            history[player].push((marble, scores[player]));
+            if leader.is_none_or(|l| l != player && scores[player] > scores[l]) {
+                leader = Some(player);
+                last_lead_change = Some(marble);
             }
-        };
-        res
+        } else {
+            marbles.rotate(rules.insert_offset);
+            marbles.insert(marble);
+        }
     }
 
-    // Rotates the focus one item clockwise
-    fn clockwise(self: &mut Self) {
-        self.ccw.push(self.focus);
-        match self.cw.pop() {
-            Some(new_focus) => self.focus = new_focus,
-            None => {
-                // It's probably much better to reuse our potentially high-capacity vecs
-                std::mem::swap(&mut self.cw, &mut self.ccw);
-                self.cw.reverse();
-                self.focus = self.cw.pop().expect("Something's gone wrong cw rotation");
-            }
-        };
+    Game {
+        scores,
+        history,
+        winner: leader.map(|l| l + 1),
+        last_lead_change,
     }
+}
 
-    // Rotates the focus one item counter_clockwise
-    fn counter_clockwise(self: &mut Self) {
-        self.cw.push(self.focus);
-        match self.ccw.pop() {
-            Some(new_focus) => self.focus = new_focus,
-            None => {
-                std::mem::swap(&mut self.cw, &mut self.ccw);
-                self.ccw.reverse();
-                self.focus = self.ccw.pop().expect("Something's gone wrong ccw rotation");
-            }
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn examples() {
+        let game = play(9, 25, &Rules::default());
+        assert_eq!(game.high_score(), 32);
+        assert_eq!(game.history[4], vec![(23, 32)]);
+        for &(players, last_marble, high_score) in &[
+            (10, 1618, 8317),
+            (13, 7999, 146_373),
+            (17, 1104, 2764),
+            (21, 6111, 54718),
+            (30, 5807, 37305),
+        ] {
+            assert_eq!(solve_better(players, last_marble), high_score);
+            assert_eq!(solve(players, last_marble), high_score);
+        }
     }
 
-    // Insert the new element in the focus, pushing the current focus cw
-    fn push(self: &mut Self, elem: u32) {
-        self.cw.push(self.focus);
-        self.focus = elem;
+    #[test]
+    fn winner_and_lead_changes() {
+        let game = play(9, 25, &Rules::default());
+        assert_eq!(game.winner, Some(5));
+        assert_eq!(game.last_lead_change, Some(23));
+
+        let game = play(10, 1618, &Rules::default());
+        assert_eq!(game.winner, Some(10));
+        assert_eq!(game.last_lead_change, Some(1610));
+
+        // Nobody scores before marble 23
+        let game = play(9, 22, &Rules::default());
+        assert_eq!(game.winner, None);
+        assert_eq!(game.last_lead_change, None);
     }
 
-    #[allow(dead_code)]
-    fn show(self: &Self) {
-        println!(
-            "{:?} ={:?}= {:?}",
-            self.ccw,
-            self.focus,
-            self.cw.iter().rev()
-        )
+    #[test]
+    fn every_marble_scores() {
+        // The first marble takes the 0 marble with it, and after that the
+        // circle is empty so each marble scores just itself
+        let rules = Rules {
+            special_every: 1,
+            removal_offset: 0,
+            insert_offset: 1,
+        };
+        let game = play(3, 10, &rules);
+        assert_eq!(game.scores, vec![22, 15, 18]);
+        assert_eq!(
+            game.history,
+            vec![
+                vec![(1, 1), (4, 5), (7, 12), (10, 22)],
+                vec![(2, 2), (5, 7), (8, 15)],
+                vec![(3, 3), (6, 9), (9, 18)],
+            ]
+        );
+        assert_eq!(game.winner, Some(1));
+        assert_eq!(game.last_lead_change, Some(10));
     }
 }
@@ -0,0 +1,231 @@
+use std::io::{self, Write};
+
+pub type Rgb = [u8; 3];
+
+pub const BLACK: Rgb = [0, 0, 0];
+pub const WHITE: Rgb = [255, 255, 255];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Png,
+    Ppm,
+    // One rect per non-background pixel, so only sensible for sparse images
+    Svg,
+}
+
+impl Format {
+    pub fn from_path(path: &str) -> Option<Format> {
+        let ext = path.rsplit('.').next()?.to_lowercase();
+        match ext.as_str() {
+            "png" => Some(Format::Png),
+            "ppm" => Some(Format::Ppm),
+            "svg" => Some(Format::Svg),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Raster {
+    pub width: usize,
+    pub height: usize,
+    pub background: Rgb,
+    pixels: Vec<Rgb>,
+}
+
+impl Raster {
+    pub fn new(width: usize, height: usize, background: Rgb) -> Raster {
+        Raster {
+            width,
+            height,
+            background,
+            pixels: vec![background; width * height],
+        }
+    }
+
+    pub fn from_fn(width: usize, height: usize, f: impl Fn(usize, usize) -> Rgb) -> Raster {
+        let mut raster = Raster::new(width, height, BLACK);
+        for y in 0..height {
+            for x in 0..width {
+                raster.set(x, y, f(x, y));
+            }
+        }
+        raster
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, colour: Rgb) {
+        self.pixels[y * self.width + x] = colour;
+    }
+
+    // Blows every pixel up into a scale x scale block
+    pub fn scaled(&self, scale: usize) -> Raster {
+        let scale = std::cmp::max(scale, 1);
+        let mut res = Raster::new(self.width * scale, self.height * scale, self.background);
+        for y in 0..res.height {
+            for x in 0..res.width {
+                res.set(x, y, self.get(x / scale, y / scale));
+            }
+        }
+        res
+    }
+
+    pub fn write(&self, format: Format, out: &mut impl Write) -> io::Result<()> {
+        match format {
+            Format::Png => self.write_png(out),
+            Format::Ppm => self.write_ppm(out),
+            Format::Svg => self.write_svg(out),
+        }
+    }
+
+    pub fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for p in &self.pixels {
+            out.write_all(p)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_svg(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" shape-rendering="crispEdges">"#,
+            w = self.width,
+            h = self.height
+        )?;
+        writeln!(
+            out,
+            r#"<rect width="{}" height="{}" fill="{}"/>"#,
+            self.width,
+            self.height,
+            hex(self.background)
+        )?;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let p = self.get(x, y);
+                if p != self.background {
+                    writeln!(
+                        out,
+                        r#"<rect x="{}" y="{}" width="1" height="1" fill="{}"/>"#,
+                        x,
+                        y,
+                        hex(p)
+                    )?;
+                }
+            }
+        }
+        writeln!(out, "</svg>")
+    }
+
+    // Writes an 8 bit RGB PNG. The image data is stored rather than
+    // compressed, which keeps this dependency free at the cost of file size.
+    pub fn write_png(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a])?;
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // bit depth 8, colour type 2 (RGB), default compression/filter/interlace
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_chunk(out, b"IHDR", &ihdr)?;
+
+        let mut scanlines = Vec::with_capacity(self.height * (self.width * 3 + 1));
+        for row in self.pixels.chunks(std::cmp::max(self.width, 1)) {
+            scanlines.push(0); // no filter
+            for p in row {
+                scanlines.extend_from_slice(p);
+            }
+        }
+        write_chunk(out, b"IDAT", &zlib_stored(&scanlines))?;
+        write_chunk(out, b"IEND", &[])
+    }
+}
+
+fn hex([r, g, b]: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()));
+    out.write_all(&crc.to_be_bytes())
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc ^= u32::from(*b);
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut res = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        res.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        res.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        res.extend_from_slice(&len.to_le_bytes());
+        res.extend_from_slice(&(!len).to_le_bytes());
+        res.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for d in data {
+        a = (a + u32::from(*d)) % 65521;
+        b = (b + a) % 65521;
+    }
+    res.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    res
+}
+
+// Spreads ids around the hue wheel so neighbouring ids look different
+pub fn id_colour(id: usize) -> Rgb {
+    let hue = (id as f64 * 0.618_033_988_75).fract();
+    hsv(hue, 0.65, 0.95)
+}
+
+// The first n ids' colours
+pub fn id_palette(n: usize) -> Vec<Rgb> {
+    (0..n).map(id_colour).collect()
+}
+
+// n colours evenly spaced along the heat scale, blue first
+pub fn heat_palette(n: usize) -> Vec<Rgb> {
+    (0..n)
+        .map(|i| heat(i as f64 / std::cmp::max(n, 2).saturating_sub(1) as f64))
+        .collect()
+}
+
+// Blue through red for t in [0, 1]
+pub fn heat(t: f64) -> Rgb {
+    let t = t.clamp(0.0, 1.0);
+    hsv((1.0 - t) * 2.0 / 3.0, 0.9, 0.95)
+}
+
+fn hsv(h: f64, s: f64, v: f64) -> Rgb {
+    let h6 = h * 6.0;
+    let f = h6.fract();
+    let (p, q, t) = (v * (1.0 - s), v * (1.0 - s * f), v * (1.0 - s * (1.0 - f)));
+    let (r, g, b) = match h6 as u32 % 6 {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
@@ -5,6 +5,7 @@ extern crate aoc_runner_derive;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod circbuffer;
 pub mod day1;
 pub mod day10;
 pub mod day11;
@@ -28,5 +29,9 @@ pub mod day6;
 pub mod day7;
 pub mod day8;
 pub mod day9;
+pub mod image;
+pub mod player;
+pub mod sat;
+pub mod toposort;
 
 aoc_lib! { year = 2018 }
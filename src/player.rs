@@ -0,0 +1,270 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+// A simulation that can be drawn onto a character grid one frame at a time.
+pub trait Animation: Clone {
+    // Inclusive (min_x, min_y, max_x, max_y) of the current frame
+    fn bounds(&self) -> (i64, i64, i64, i64);
+    fn cell(&self, x: i64, y: i64) -> char;
+    // Advances one frame, returning false once the simulation has finished
+    fn step(&mut self) -> bool;
+    fn status(&self) -> String {
+        String::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerConfig {
+    pub fps: f64,
+    pub width: usize,
+    pub height: usize,
+    // Keep the viewport pinned to the top left of the frame's bounds
+    pub follow: bool,
+    pub paused: bool,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> PlayerConfig {
+        PlayerConfig {
+            fps: 10.0,
+            width: 120,
+            height: 50,
+            follow: true,
+            paused: false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Command {
+    TogglePause,
+    Step,
+    Back,
+    Seek(usize),
+    Fps(f64),
+    Scroll(i64, i64),
+    Follow,
+    Quit,
+}
+
+fn parse_command(line: &str) -> Option<Command> {
+    use self::Command::*;
+    let mut words = line.split_whitespace();
+    let cmd = words.next().unwrap_or("");
+    let arg = words.next();
+    let amount = arg.and_then(|a| a.parse().ok()).unwrap_or(1);
+    match cmd {
+        "" | "p" => Some(TogglePause),
+        "n" => Some(Step),
+        "b" => Some(Back),
+        "g" => arg.and_then(|a| a.parse().ok()).map(Seek),
+        "f" => arg.and_then(|a| a.parse().ok()).map(Fps),
+        "h" => Some(Scroll(-amount, 0)),
+        "l" => Some(Scroll(amount, 0)),
+        "k" => Some(Scroll(0, -amount)),
+        "j" => Some(Scroll(0, amount)),
+        "c" => Some(Follow),
+        "q" => Some(Quit),
+        _ => None,
+    }
+}
+
+fn spawn_input() -> Receiver<Command> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => break,
+            };
+            if let Some(cmd) = parse_command(&line) {
+                if tx.send(cmd).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    rx
+}
+
+// Snapshots are kept every CHECKPOINT frames so seeking backwards only has to
+// replay a short stretch rather than the whole run.
+const CHECKPOINT: usize = 64;
+
+pub struct Player<A: Animation> {
+    config: PlayerConfig,
+    current: A,
+    frame: usize,
+    // frame the simulation finished on, once we've seen it
+    last: Option<usize>,
+    checkpoints: Vec<A>,
+    origin: (i64, i64),
+}
+
+impl<A: Animation> Player<A> {
+    pub fn new(initial: A, config: PlayerConfig) -> Player<A> {
+        let (min_x, min_y, _, _) = initial.bounds();
+        Player {
+            config,
+            checkpoints: vec![initial.clone()],
+            current: initial,
+            frame: 0,
+            last: None,
+            origin: (min_x, min_y),
+        }
+    }
+
+    fn step(&mut self) -> bool {
+        if Some(self.frame) == self.last {
+            return false;
+        }
+        if !self.current.step() {
+            self.last = Some(self.frame);
+            return false;
+        }
+        self.frame += 1;
+        if self.frame.is_multiple_of(CHECKPOINT)
+            && self.frame / CHECKPOINT == self.checkpoints.len()
+        {
+            self.checkpoints.push(self.current.clone());
+        }
+        true
+    }
+
+    fn seek(&mut self, target: usize) {
+        if target < self.frame {
+            let idx = std::cmp::min(target / CHECKPOINT, self.checkpoints.len() - 1);
+            self.current = self.checkpoints[idx].clone();
+            self.frame = idx * CHECKPOINT;
+        }
+        while self.frame < target && self.step() {}
+    }
+
+    pub fn render(&self) -> String {
+        let (min_x, min_y, max_x, max_y) = self.current.bounds();
+        let (ox, oy) = if self.config.follow {
+            (min_x, min_y)
+        } else {
+            self.origin
+        };
+        // Clip the viewport to the frame so small grids don't fill the screen
+        let width = std::cmp::min(self.config.width as i64, max_x - ox + 1);
+        let height = std::cmp::min(self.config.height as i64, max_y - oy + 1);
+
+        let mut out = String::new();
+        for y in oy..oy + height {
+            for x in ox..ox + width {
+                out.push(self.current.cell(x, y));
+            }
+            out.push('\n');
+        }
+        out.push_str(&format!(
+            "frame {}{} | view {},{} of {},{}..{},{} | {:.1} fps | {}\n",
+            self.frame,
+            if Some(self.frame) == self.last {
+                " (end)"
+            } else {
+                ""
+            },
+            ox,
+            oy,
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+            self.config.fps,
+            self.current.status()
+        ));
+        out
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        // Home the cursor and clear the screen before every frame
+        write!(out, "\x1b[H\x1b[2J{}", self.render())?;
+        writeln!(
+            out,
+            "[enter/p] pause  [n] step  [b] back  [g N] seek  [f N] fps  [hjkl N] scroll  [c] follow  [q] quit"
+        )?;
+        out.flush()
+    }
+
+    fn apply(&mut self, cmd: Command, paused: &mut bool) -> bool {
+        use self::Command::*;
+        match cmd {
+            TogglePause => *paused = !*paused,
+            Step => {
+                *paused = true;
+                self.step();
+            }
+            Back => {
+                *paused = true;
+                let target = self.frame.saturating_sub(1);
+                self.seek(target);
+            }
+            Seek(n) => self.seek(n),
+            Fps(f) if f > 0.0 => self.config.fps = f,
+            Fps(_) => (),
+            Scroll(dx, dy) => {
+                if self.config.follow {
+                    let (min_x, min_y, _, _) = self.current.bounds();
+                    self.origin = (min_x, min_y);
+                    self.config.follow = false;
+                }
+                self.origin = (self.origin.0 + dx, self.origin.1 + dy);
+            }
+            Follow => self.config.follow = true,
+            Quit => return false,
+        }
+        true
+    }
+
+    // Plays the animation on stdout, taking commands from stdin one line at a
+    // time, until the user quits or stdin closes after the last frame.
+    pub fn play(&mut self) -> io::Result<()> {
+        let input = spawn_input();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        let mut paused = self.config.paused;
+        let mut input_open = true;
+
+        loop {
+            self.draw(&mut out)?;
+            let finished = Some(self.frame) == self.last;
+            let cmd = if paused || finished {
+                if !input_open {
+                    return Ok(());
+                }
+                match input.recv() {
+                    Ok(cmd) => Some(cmd),
+                    Err(_) => {
+                        input_open = false;
+                        None
+                    }
+                }
+            } else {
+                let delay = Duration::from_millis((1000.0 / self.config.fps) as u64);
+                match input.recv_timeout(delay) {
+                    Ok(cmd) => Some(cmd),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        input_open = false;
+                        thread::sleep(delay);
+                        None
+                    }
+                }
+            };
+
+            match cmd {
+                Some(cmd) if !self.apply(cmd, &mut paused) => return Ok(()),
+                Some(_) => (),
+                None if !paused => {
+                    self.step();
+                }
+                None => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,344 @@
+// Summed-area table over a rectangular grid, giving the sum of any
+// axis-aligned rectangle in constant time.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SummedAreaTable {
+    width: usize,
+    height: usize,
+    // (width + 1) x (height + 1), with an empty first row and column so
+    // lookups never need bounds checks
+    table: Vec<i64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Square {
+    pub x: usize,
+    pub y: usize,
+    pub size: usize,
+    pub sum: i64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub sum: i64,
+}
+
+// Inclusive bounds on the size of rectangle a search may return
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SizeLimits {
+    pub min_width: usize,
+    pub max_width: usize,
+    pub min_height: usize,
+    pub max_height: usize,
+}
+
+impl SizeLimits {
+    pub fn any() -> SizeLimits {
+        SizeLimits {
+            min_width: 1,
+            max_width: usize::MAX,
+            min_height: 1,
+            max_height: usize::MAX,
+        }
+    }
+}
+
+impl SummedAreaTable {
+    pub fn new<T: Copy + Into<i64>>(rows: &[Vec<T>]) -> SummedAreaTable {
+        let width = rows.first().map_or(0, |r| r.len());
+        SummedAreaTable::from_fn(width, rows.len(), |x, y| rows[y][x].into())
+    }
+
+    pub fn from_fn(
+        width: usize,
+        height: usize,
+        f: impl Fn(usize, usize) -> i64,
+    ) -> SummedAreaTable {
+        let stride = width + 1;
+        let mut table = vec![0; stride * (height + 1)];
+        for y in 0..height {
+            let mut row_sum = 0;
+            for x in 0..width {
+                row_sum += f(x, y);
+                table[(y + 1) * stride + x + 1] = row_sum + table[y * stride + x + 1];
+            }
+        }
+        SummedAreaTable {
+            width,
+            height,
+            table,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn at(&self, x: usize, y: usize) -> i64 {
+        self.table[y * (self.width + 1) + x]
+    }
+
+    // Sum of the w x h rectangle with its top left corner at (x, y)
+    pub fn sum(&self, x: usize, y: usize, w: usize, h: usize) -> i64 {
+        assert!(x + w <= self.width && y + h <= self.height);
+        self.at(x + w, y + h) + self.at(x, y) - self.at(x + w, y) - self.at(x, y + h)
+    }
+
+    // Ties go to the smallest y, then the smallest x
+    pub fn best_square(&self, size: usize) -> Option<Square> {
+        self.best_square_in_rows(size..=size, 0..self.height)
+    }
+
+    // Ties go to the smallest y, then the smallest x, then the smallest size
+    pub fn best_square_any(&self) -> Option<Square> {
+        self.best_square_in_rows(1..=std::cmp::min(self.width, self.height), 0..self.height)
+    }
+
+    // Same result as best_square_any, with the rows dealt out round robin
+    // across threads (the top rows have the most sizes to try, so handing out
+    // contiguous blocks would leave the last threads idle)
+    pub fn best_square_any_parallel(&self, threads: usize) -> Option<Square> {
+        let threads = std::cmp::max(threads, 1);
+        let sizes = 1..=std::cmp::min(self.width, self.height);
+
+        let found: Vec<Square> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let sizes = sizes.clone();
+                    scope.spawn(move || {
+                        (t..self.height)
+                            .step_by(threads)
+                            .filter_map(|y| self.best_square_in_rows(sizes.clone(), y..y + 1))
+                            .collect::<Vec<Square>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+
+        // The sequential scan keeps the first best it sees, so break ties on
+        // the smallest y, x and size
+        found.into_iter().min_by_key(|s| (-s.sum, s.y, s.x, s.size))
+    }
+
+    // Best square with a size in `sizes` whose top edge is in `rows`, so a
+    // search can be split up by row
+    pub fn best_square_in_rows(
+        &self,
+        sizes: std::ops::RangeInclusive<usize>,
+        rows: std::ops::Range<usize>,
+    ) -> Option<Square> {
+        let mut best: Option<Square> = None;
+        for y in rows {
+            for x in 0..self.width {
+                let max_size = std::cmp::min(self.width - x, self.height - y);
+                for size in *sizes.start()..=std::cmp::min(*sizes.end(), max_size) {
+                    let sum = self.sum(x, y, size, size);
+                    if best.is_none_or(|b| sum > b.sum) {
+                        best = Some(Square { x, y, size, sum });
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    // Best rectangle of any shape within the limits. For every band of rows
+    // this runs Kadane's algorithm over the column prefix sums, using a sliding
+    // window minimum to keep the width in range, so it's O(height^2 * width).
+    // Ties go to the first found scanning by top edge, height, then right edge.
+    pub fn best_rect(&self, limits: SizeLimits) -> Option<Rect> {
+        use std::cmp::{max, min};
+        use std::collections::VecDeque;
+
+        let min_width = max(limits.min_width, 1);
+        let max_width = min(limits.max_width, self.width);
+        let min_height = max(limits.min_height, 1);
+        let mut best: Option<Rect> = None;
+        let mut prefix = vec![0; self.width + 1];
+        let mut window: VecDeque<usize> = VecDeque::new();
+
+        for y in 0..self.height {
+            for height in min_height..=min(limits.max_height, self.height - y) {
+                for (j, p) in prefix.iter_mut().enumerate() {
+                    *p = self.sum(0, y, j, height);
+                }
+                window.clear();
+                for right in min_width..=self.width {
+                    // Left edges far enough back to give at least min_width
+                    let i = right - min_width;
+                    while window.back().is_some_and(|&b| prefix[b] > prefix[i]) {
+                        window.pop_back();
+                    }
+                    window.push_back(i);
+                    // and not so far back the rectangle is wider than max_width
+                    while window.front().is_some_and(|&f| f + max_width < right) {
+                        window.pop_front();
+                    }
+
+                    if let Some(&left) = window.front() {
+                        let sum = prefix[right] - prefix[left];
+                        if best.is_none_or(|b| sum > b.sum) {
+                            best = Some(Rect {
+                                x: left,
+                                y,
+                                width: right - left,
+                                height,
+                                sum,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_grids() -> Vec<Vec<Vec<i64>>> {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut rand = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        (0..60)
+            .map(|_| {
+                let (width, height) = (1 + rand(8) as usize, 1 + rand(8) as usize);
+                // Only a few different values, so there are plenty of ties
+                let spread = 1 + rand(5);
+                (0..height)
+                    .map(|_| {
+                        (0..width)
+                            .map(|_| rand(2 * spread + 1) as i64 - spread as i64)
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Every rectangle in the order best_rect breaks ties in
+    fn brute_force_rect(grid: &[Vec<i64>], limits: SizeLimits) -> Option<Rect> {
+        let (width, height) = (grid[0].len(), grid.len());
+        let mut best: Option<Rect> = None;
+        for y in 0..height {
+            for h in 1..=height - y {
+                for right in 1..=width {
+                    for x in 0..right {
+                        let w = right - x;
+                        if w < limits.min_width
+                            || w > limits.max_width
+                            || h < limits.min_height
+                            || h > limits.max_height
+                        {
+                            continue;
+                        }
+                        let sum = grid[y..y + h]
+                            .iter()
+                            .map(|row| row[x..right].iter().sum::<i64>())
+                            .sum();
+                        if best.is_none_or(|b| sum > b.sum) {
+                            best = Some(Rect {
+                                x,
+                                y,
+                                width: w,
+                                height: h,
+                                sum,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn best_rect_matches_brute_force() {
+        let mut limits = vec![SizeLimits::any()];
+        for &(min_width, max_width, min_height, max_height) in &[
+            (1, 1, 1, 1),
+            (2, 3, 1, 8),
+            (1, 8, 3, 3),
+            (3, 5, 2, 4),
+            (4, 2, 1, 8),
+            (1, 8, 5, 4),
+            (9, 9, 1, 1),
+        ] {
+            limits.push(SizeLimits {
+                min_width,
+                max_width,
+                min_height,
+                max_height,
+            });
+        }
+
+        for grid in random_grids() {
+            let sat = SummedAreaTable::new(&grid);
+            for &limits in &limits {
+                assert_eq!(
+                    sat.best_rect(limits),
+                    brute_force_rect(&grid, limits),
+                    "{:?} {:?}",
+                    limits,
+                    grid
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn best_rect_with_impossible_limits() {
+        let sat = SummedAreaTable::new(&[vec![1, 2, 3], vec![4, 5, 6]]);
+        let limits = SizeLimits {
+            min_width: 3,
+            max_width: 2,
+            ..SizeLimits::any()
+        };
+        assert_eq!(sat.best_rect(limits), None);
+        let limits = SizeLimits {
+            min_height: 3,
+            ..SizeLimits::any()
+        };
+        assert_eq!(sat.best_rect(limits), None);
+    }
+
+    #[test]
+    fn parallel_squares_match_sequential() {
+        // Plus grids where every square of the same size ties
+        let mut grids = random_grids();
+        grids.push(vec![vec![0; 7]; 5]);
+        grids.push(vec![vec![1; 6]; 6]);
+        grids.push(vec![vec![-1; 4]; 9]);
+
+        for grid in grids {
+            let sat = SummedAreaTable::new(&grid);
+            let expected = sat.best_square_any();
+            for threads in &[0, 1, 2, 3, 4, 7, 16] {
+                assert_eq!(
+                    sat.best_square_any_parallel(*threads),
+                    expected,
+                    "{} threads on {:?}",
+                    threads,
+                    grid
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+// Dependency graph over named tasks. Tasks are numbered in the order they're
+// first mentioned.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Graph {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    dependents: Vec<Vec<usize>>,
+    dependencies: Vec<Vec<usize>>,
+}
+
+// How to choose between tasks that are ready at the same time
+pub enum TieBreak<'a> {
+    Lexicographic,
+    Reverse,
+    // Lowest value first, then lexicographic among equal priorities
+    Priority(&'a dyn Fn(&str) -> i64),
+}
+
+impl<'a> TieBreak<'a> {
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            TieBreak::Lexicographic => a.cmp(b),
+            TieBreak::Reverse => b.cmp(a),
+            TieBreak::Priority(f) => f(a).cmp(&f(b)).then_with(|| a.cmp(b)),
+        }
+    }
+}
+
+// A set of tasks that all (transitively) depend on each other, listed so each
+// depends on the one before it and the first depends on the last
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cycle(pub Vec<String>);
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "dependency cycle: {}", self.0.join(" -> "))?;
+        if let Some(first) = self.0.first() {
+            write!(f, " -> {}", first)?;
+        }
+        Ok(())
+    }
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph::default()
+    }
+
+    pub fn add_task(&mut self, name: &str) -> usize {
+        if let Some(&i) = self.index.get(name) {
+            return i;
+        }
+        let i = self.names.len();
+        self.names.push(name.to_owned());
+        self.index.insert(name.to_owned(), i);
+        self.dependents.push(Vec::new());
+        self.dependencies.push(Vec::new());
+        i
+    }
+
+    // `before` must be finished before `after` can begin
+    pub fn add_dependency(&mut self, before: &str, after: &str) {
+        let b = self.add_task(before);
+        let a = self.add_task(after);
+        self.dependents[b].push(a);
+        self.dependencies[a].push(b);
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn name(&self, task: usize) -> &str {
+        &self.names[task]
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.index.get(name).cloned()
+    }
+
+    pub fn dependents(&self, task: usize) -> &[usize] {
+        &self.dependents[task]
+    }
+
+    pub fn dependencies(&self, task: usize) -> &[usize] {
+        &self.dependencies[task]
+    }
+
+    // Position of every task when they're all ordered by the tie break, so
+    // the ready set can be kept sorted by plain integers
+    pub fn ranks(&self, tie_break: &TieBreak) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.sort_by(|a, b| tie_break.compare(&self.names[*a], &self.names[*b]));
+        let mut rank = vec![0; self.len()];
+        for (r, &task) in order.iter().enumerate() {
+            rank[task] = r;
+        }
+        rank
+    }
+
+    // Kahn's algorithm, always taking the first ready task by the tie break
+    pub fn sort(&self, tie_break: &TieBreak) -> Result<Vec<String>, Cycle> {
+        self.sort_indices(tie_break)
+            .map(|order| order.into_iter().map(|i| self.names[i].clone()).collect())
+    }
+
+    pub fn sort_indices(&self, tie_break: &TieBreak) -> Result<Vec<usize>, Cycle> {
+        let rank = self.ranks(tie_break);
+        let mut by_rank = vec![0; self.len()];
+        for (task, &r) in rank.iter().enumerate() {
+            by_rank[r] = task;
+        }
+
+        let mut waiting: Vec<usize> = self.dependencies.iter().map(|d| d.len()).collect();
+        let mut ready: BTreeSet<usize> = (0..self.len())
+            .filter(|&t| waiting[t] == 0)
+            .map(|t| rank[t])
+            .collect();
+        let mut res = Vec::with_capacity(self.len());
+
+        while let Some(&r) = ready.iter().next() {
+            ready.remove(&r);
+            let task = by_rank[r];
+            res.push(task);
+            for &dep in &self.dependents[task] {
+                waiting[dep] -= 1;
+                if waiting[dep] == 0 {
+                    ready.insert(rank[dep]);
+                }
+            }
+        }
+
+        if res.len() < self.len() {
+            return Err(self.find_cycle(&waiting));
+        }
+        Ok(res)
+    }
+
+    // Everything still waiting after a sort has a dependency that's also still
+    // waiting, so walking back through those must eventually loop
+    fn find_cycle(&self, waiting: &[usize]) -> Cycle {
+        let mut seen_at: HashMap<usize, usize> = HashMap::new();
+        let mut path: Vec<usize> = Vec::new();
+        let mut task = (0..self.len()).find(|&t| waiting[t] > 0).unwrap();
+        loop {
+            if let Some(&start) = seen_at.get(&task) {
+                let mut cycle: Vec<String> = path[start..]
+                    .iter()
+                    .map(|&t| self.names[t].clone())
+                    .collect();
+                // We walked against the arrows
+                cycle.reverse();
+                return Cycle(cycle);
+            }
+            seen_at.insert(task, path.len());
+            path.push(task);
+            task = *self.dependencies[task]
+                .iter()
+                .find(|&&d| waiting[d] > 0)
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &str)]) -> Graph {
+        let mut graph = Graph::new();
+        for (before, after) in edges {
+            graph.add_dependency(before, after);
+        }
+        graph
+    }
+
+    // The day 7 example
+    fn example() -> Graph {
+        graph(&[
+            ("C", "A"),
+            ("C", "F"),
+            ("A", "B"),
+            ("A", "D"),
+            ("B", "E"),
+            ("D", "E"),
+            ("F", "E"),
+        ])
+    }
+
+    fn names(order: &[&str]) -> Vec<String> {
+        order.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn tie_breaks() {
+        let graph = example();
+        assert_eq!(
+            graph.sort(&TieBreak::Lexicographic),
+            Ok(names(&["C", "A", "B", "D", "F", "E"]))
+        );
+        assert_eq!(
+            graph.sort(&TieBreak::Reverse),
+            Ok(names(&["C", "F", "A", "D", "B", "E"]))
+        );
+        let f_first = |n: &str| if n == "F" { -1 } else { 0 };
+        assert_eq!(
+            graph.sort(&TieBreak::Priority(&f_first)),
+            Ok(names(&["C", "F", "A", "B", "D", "E"]))
+        );
+    }
+
+    #[test]
+    fn three_cycle() {
+        // X is ready straight away, but everything else waits on the cycle
+        let graph = graph(&[("A", "B"), ("B", "C"), ("C", "A"), ("C", "D"), ("X", "D")]);
+        let cycle = graph.sort(&TieBreak::Lexicographic).unwrap_err();
+        assert_eq!(cycle, Cycle(names(&["B", "C", "A"])));
+        assert_eq!(cycle.to_string(), "dependency cycle: B -> C -> A -> B");
+    }
+
+    #[test]
+    fn self_dependency() {
+        let graph = graph(&[("A", "A"), ("B", "A")]);
+        let cycle = graph.sort(&TieBreak::Lexicographic).unwrap_err();
+        assert_eq!(cycle, Cycle(names(&["A"])));
+        assert_eq!(cycle.to_string(), "dependency cycle: A -> A");
+    }
+}